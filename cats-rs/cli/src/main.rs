@@ -1,11 +1,29 @@
-use packing::packing::pack;
+use packing::dump::{assemble, dump};
+use packing::editing::{add, merge, remove, rename, Conflict};
+use packing::manifest::Manifest;
+use packing::packing::{pack, pack_manifest};
+use packing::signing::{load_signing_key, sign};
+use packing::tarball::pack_tar;
+use packing::zipfile::pack_zip;
+use unpacking::diff::{diff, to_json};
+use unpacking::layout::{layout, summary};
+use unpacking::reader::CatReader;
+use unpacking::signature::{load_verifying_key, verify};
+use unpacking::tarball::unpack_tar;
+use unpacking::zipfile::unpack_zip;
 use unpacking::unpacking::unpack;
-use clap::{Arg, ArgMatches};
+use clap::builder::ArgPredicate;
+use clap::{value_parser, Arg, ArgMatches};
 use clap::{ArgAction, Command};
 use meta::error::{CatError, Result};
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::{env, fs, io};
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use meta::Context;
+use meta::metadata::Compression;
+use meta::{Context, Solid};
 
 fn main() {
     let matches = Command::new("Cats Archiver")
@@ -19,14 +37,471 @@ fn main() {
                         .long("verbose")
                         .action(ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("permissions")
+                        .long("no-permissions")
+                        .action(ArgAction::SetFalse),
+                )
+                .arg(
+                    Arg::new("times")
+                        .long("no-times")
+                        .action(ArgAction::SetFalse),
+                )
+                .arg(
+                    Arg::new("verify_key")
+                        .long("verify-key")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("destination")
+                        .required(false)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("add")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("compression")
+                        .short('c')
+                        .long("compression")
+                        .value_parser(["gzip", "zstd", "none"])
+                        .default_value("gzip")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("level")
+                        .short('l')
+                        .long("level")
+                        .value_parser(value_parser!(u32).range(1..=22))
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("prefix")
+                        .long("prefix")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .required(true)
+                        .num_args(1..)
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("rm")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("compact")
+                        .long("compact")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("patterns")
+                        .required(true)
+                        .num_args(1..)
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("mv")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("from")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("to")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("merge")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("conflict")
+                        .long("conflict")
+                        .value_parser(["first", "last", "error"])
+                        .default_value("error")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("encrypt_header")
+                        .long("encrypt-header")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("sources")
+                        .required(true)
+                        .num_args(1..)
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("before")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("after")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("from-tar")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("compression")
+                        .short('c')
+                        .long("compression")
+                        .value_parser(["gzip", "zstd", "none"])
+                        .default_value("gzip")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("level")
+                        .short('l')
+                        .long("level")
+                        .value_parser(value_parser!(u32).range(1..=22))
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("permissions")
+                        .long("permissions")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("times")
+                        .long("times")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("source")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("to-tar")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("gzip")
+                        .short('z')
+                        .long("gzip")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("output")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("from-zip")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("compression")
+                        .short('c')
+                        .long("compression")
+                        .value_parser(["gzip", "zstd", "none"])
+                        .default_value("gzip")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("level")
+                        .short('l')
+                        .long("level")
+                        .value_parser(value_parser!(u32).range(1..=22))
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_parser(value_parser!(usize))
+                        .default_value("0")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("permissions")
+                        .long("permissions")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("times")
+                        .long("times")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("source")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("to-zip")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("output")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("dump")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("output_dir")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("assemble")
+                .arg(
+                    Arg::new("verbose")
+                        .short('v')
+                        .long("verbose")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("input_dir")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
                 .arg(
                     Arg::new("archive_name")
                         .required(true)
                         .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .arg(
+                    Arg::new("layout")
+                        .long("layout")
+                        .action(ArgAction::SetTrue),
                 )
                 .arg(
-                    Arg::new("destination")
-                        .required(false)
+                    Arg::new("password_file")
+                        .long("password-file")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("sign")
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-signature")
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("archive_name")
+                        .required(true)
                         .action(ArgAction::Set),
                 ),
         )
@@ -41,8 +516,132 @@ fn main() {
             Arg::new("gzip")
                 .short('n')
                 .long("no-gzip")
+                .conflicts_with("compression")
                 .action(ArgAction::SetFalse),
         )
+        .arg(
+            Arg::new("compression")
+                .short('c')
+                .long("compression")
+                .value_parser(["gzip", "zstd", "none"])
+                .default_value("gzip")
+                .default_value_if("dictionary", ArgPredicate::IsPresent, "zstd")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("level")
+                .short('l')
+                .long("level")
+                .value_parser(value_parser!(u32).range(1..=22))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("dictionary")
+                .short('d')
+                .long("dictionary")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dictionary_size")
+                .long("dictionary-size")
+                .value_parser(value_parser!(usize))
+                .default_value("112640")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_parser(value_parser!(usize))
+                .default_value("0")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("solid")
+                .short('s')
+                .long("solid")
+                .value_parser(["directory", "extension"])
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("block_size")
+                .long("block-size")
+                .value_parser(value_parser!(usize))
+                .default_value("4194304")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("chunking")
+                .long("chunking")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("chunk_size")
+                .long("chunk-size")
+                .value_parser(value_parser!(u32).range(256..=4194304))
+                .default_value("65536")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("checksums")
+                .long("checksums")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("permissions")
+                .long("permissions")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("times")
+                .long("times")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("symlinks")
+                .long("symlinks")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("manifest")
+                .short('m')
+                .long("manifest")
+                .conflicts_with("input_dir")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("manifest_format")
+                .long("manifest-format")
+                .value_parser(["text", "json"])
+                .requires("manifest")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("encrypt")
+                .short('e')
+                .long("encrypt")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("encrypt_header")
+                .long("encrypt-header")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("password_file")
+                .long("password-file")
+                .action(ArgAction::Set),
+        )
         .arg(
             Arg::new("archive_name")
                 .required(true)
@@ -83,32 +682,486 @@ fn handle(matches: ArgMatches) -> Result<()> {
                 archive_name,
                 &Context {
                     verbose: command.get_flag("verbose"),
-                    gzip: false,
+                    jobs: *command
+                        .get_one::<usize>("jobs")
+                        .expect("Expected jobs to have a default!"),
+                    verifying_key: match command.get_one::<String>("verify_key") {
+                        Some(key) => Some(load_verifying_key(Path::new(key))?),
+                        None => None,
+                    },
+                    permissions: command.get_flag("permissions"),
+                    times: command.get_flag("times"),
+                    password: password(command)?,
+                    ..Default::default()
                 },
             )
         }
 
-        None => {
+        Some(("add", command)) => {
             let archive_name = Path::new(
-                matches
+                command
                     .get_one::<String>("archive_name")
                     .expect("Expected archive name to be present!"),
             );
+            let prefix = command.get_one::<String>("prefix").map(|prefix| prefix.trim_end_matches('/'));
 
-            let input = match matches.get_one::<String>("input_dir") {
-                Some(file) => Path::new(file),
-                None => Path::new("../.."),
-            };
+            // Every path is added under its own name, below the prefix if one was given.
+            let entries = command
+                .get_many::<String>("paths")
+                .expect("Expected paths to be present!")
+                .map(|path| {
+                    let path = PathBuf::from(path);
+                    let name = match path.file_name().and_then(|name| name.to_str()) {
+                        Some(name) => name.to_string(),
+                        None => return Err(CatError::InvalidInput(path.display().to_string())),
+                    };
+                    match prefix {
+                        Some(prefix) => Ok((format!("{prefix}/{name}"), path)),
+                        None => Ok((name, path)),
+                    }
+                })
+                .collect::<Result<Vec<(String, PathBuf)>>>()?;
+
+            add(
+                archive_name,
+                &Manifest { entries },
+                &Context {
+                    verbose: command.get_flag("verbose"),
+                    compression: match command.get_one::<String>("compression").map(String::as_str) {
+                        Some("zstd") => Compression::Zstd,
+                        Some("none") => Compression::None,
+                        _ => Compression::Gzip,
+                    },
+                    level: command.get_one::<u32>("level").copied(),
+                    jobs: *command
+                        .get_one::<usize>("jobs")
+                        .expect("Expected jobs to have a default!"),
+                    password: password(command)?,
+                    ..Default::default()
+                },
+            )
+        }
+
+        Some(("rm", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let patterns = command
+                .get_many::<String>("patterns")
+                .expect("Expected patterns to be present!")
+                .cloned()
+                .collect::<Vec<String>>();
+
+            remove(
+                archive_name,
+                &patterns,
+                command.get_flag("compact"),
+                &Context {
+                    verbose: command.get_flag("verbose"),
+                    password: password(command)?,
+                    ..Default::default()
+                },
+            )
+        }
+
+        Some(("mv", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+
+            rename(
+                archive_name,
+                command.get_one::<String>("from").expect("Expected source to be present!"),
+                command.get_one::<String>("to").expect("Expected target to be present!"),
+                &Context {
+                    verbose: command.get_flag("verbose"),
+                    password: password(command)?,
+                    ..Default::default()
+                },
+            )
+        }
+
+        Some(("merge", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let sources = command
+                .get_many::<String>("sources")
+                .expect("Expected sources to be present!")
+                .map(PathBuf::from)
+                .collect::<Vec<PathBuf>>();
 
-            pack(
-                input,
+            merge(
                 archive_name,
+                &sources,
+                match command.get_one::<String>("conflict").map(String::as_str) {
+                    Some("first") => Conflict::First,
+                    Some("last") => Conflict::Last,
+                    _ => Conflict::Error,
+                },
                 &Context {
-                    verbose: matches.get_flag("verbose"),
-                    gzip: matches.get_flag("gzip"),
+                    verbose: command.get_flag("verbose"),
+                    jobs: *command
+                        .get_one::<usize>("jobs")
+                        .expect("Expected jobs to have a default!"),
+                    password: password(command)?,
+                    encrypt_header: command.get_flag("encrypt_header"),
+                    ..Default::default()
                 },
             )
         }
+
+        Some(("diff", command)) => {
+            let context = Context {
+                password: password(command)?,
+                ..Default::default()
+            };
+            let before = CatReader::open_with(
+                Path::new(command.get_one::<String>("before").expect("Expected archive to be present!")),
+                &context,
+            )?;
+            let after = CatReader::open_with(
+                Path::new(command.get_one::<String>("after").expect("Expected archive to be present!")),
+                &context,
+            )?;
+
+            let differences = diff(&before, &after)?;
+            match command.get_flag("json") {
+                true => println!("{}", to_json(&differences)),
+                false => {
+                    for difference in differences {
+                        println!("{difference}");
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        Some(("from-tar", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let source = command.get_one::<String>("source").expect("Expected source to be present!");
+            let context = conversion_context(command)?;
+
+            match source.as_str() {
+                "-" => pack_tar(io::stdin().lock(), "stdin", archive_name, &context),
+                path => {
+                    let file = File::open(path).map_err(|err| CatError::FailedToOpenInput {
+                        path: path.to_string(),
+                        error: err.to_string(),
+                    })?;
+                    pack_tar(file, path, archive_name, &context)
+                }
+            }
+        }
+
+        Some(("from-zip", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let source = Path::new(command.get_one::<String>("source").expect("Expected source to be present!"));
+
+            pack_zip(source, archive_name, &conversion_context(command)?)
+        }
+
+        Some(("to-zip", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let output = command.get_one::<String>("output").expect("Expected output to be present!");
+            let context = Context {
+                verbose: command.get_flag("verbose"),
+                password: password(command)?,
+                ..Default::default()
+            };
+            let reader = CatReader::open_with(archive_name, &context)?;
+
+            let error = |err: io::Error| CatError::ErrorWritingFile {
+                path: output.clone(),
+                error: err.to_string(),
+            };
+            let file = File::create(output).map_err(error)?;
+            unpack_zip(&reader, BufWriter::new(file), &context)?.flush().map_err(error)
+        }
+
+        Some(("to-tar", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let output = command.get_one::<String>("output").expect("Expected output to be present!");
+            let context = Context {
+                verbose: command.get_flag("verbose"),
+                password: password(command)?,
+                ..Default::default()
+            };
+            let reader = CatReader::open_with(archive_name, &context)?;
+
+            let error = |err: io::Error| CatError::ErrorWritingFile {
+                path: output.clone(),
+                error: err.to_string(),
+            };
+            let writer: Box<dyn Write> = match output.as_str() {
+                "-" => Box::new(io::stdout().lock()),
+                path => Box::new(BufWriter::new(File::create(path).map_err(error)?)),
+            };
+            let gzip = command.get_flag("gzip") || output.ends_with(".gz") || output.ends_with(".tgz");
+            match gzip {
+                true => {
+                    unpack_tar(&reader, GzEncoder::new(writer, flate2::Compression::default()), &context)?
+                        .finish()
+                        .and_then(|mut writer| writer.flush())
+                        .map_err(error)
+                }
+                false => unpack_tar(&reader, writer, &context)?.flush().map_err(error),
+            }
+        }
+
+        Some(("dump", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let output = Path::new(
+                command
+                    .get_one::<String>("output_dir")
+                    .expect("Expected output directory to be present!"),
+            );
+            let context = Context {
+                verbose: command.get_flag("verbose"),
+                password: password(command)?,
+                ..Default::default()
+            };
+
+            dump(archive_name, output, &context)
+        }
+
+        Some(("assemble", command)) => {
+            let input = Path::new(
+                command
+                    .get_one::<String>("input_dir")
+                    .expect("Expected input directory to be present!"),
+            );
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let context = Context {
+                verbose: command.get_flag("verbose"),
+                ..Default::default()
+            };
+
+            assemble(input, archive_name, &context)
+        }
+
+        Some(("inspect", command)) => {
+            let archive_name = command
+                .get_one::<String>("archive_name")
+                .expect("Expected archive name to be present!");
+            let context = Context {
+                password: password(command)?,
+                ..Default::default()
+            };
+
+            let content = fs::read(archive_name).map_err(|err| CatError::FailedToOpenInput {
+                path: archive_name.clone(),
+                error: err.to_string(),
+            })?;
+            match command.get_flag("layout") {
+                true => print!("{}", layout(&content, &context)?),
+                false => print!("{}", summary(&content, &context)?),
+            }
+            Ok(())
+        }
+
+        Some(("sign", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let key = load_signing_key(Path::new(
+                command.get_one::<String>("key").expect("Expected key to be present!"),
+            ))?;
+
+            sign(archive_name, &key)
+        }
+
+        Some(("verify-signature", command)) => {
+            let archive_name = Path::new(
+                command
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+            let key = load_verifying_key(Path::new(
+                command.get_one::<String>("key").expect("Expected key to be present!"),
+            ))?;
+
+            let content = fs::read(archive_name).map_err(|err| {
+                CatError::FailedToOpenInput {
+                    path: archive_name.display().to_string(),
+                    error: err.to_string(),
+                }
+            })?;
+            verify(&content, &key)?;
+            println!("Signature of {} is valid", archive_name.display());
+            Ok(())
+        }
+
+        None => {
+            let archive_name = Path::new(
+                matches
+                    .get_one::<String>("archive_name")
+                    .expect("Expected archive name to be present!"),
+            );
+
+            let context = Context {
+                verbose: matches.get_flag("verbose"),
+                compression: match matches.get_one::<String>("compression").map(String::as_str) {
+                    _ if !matches.get_flag("gzip") => Compression::None,
+                    Some("zstd") => Compression::Zstd,
+                    Some("none") => Compression::None,
+                    _ => Compression::Gzip,
+                },
+                level: matches.get_one::<u32>("level").copied(),
+                jobs: *matches
+                    .get_one::<usize>("jobs")
+                    .expect("Expected jobs to have a default!"),
+                dictionary_size: match matches.get_flag("dictionary") {
+                    true => *matches
+                        .get_one::<usize>("dictionary_size")
+                        .expect("Expected dictionary size to have a default!"),
+                    false => 0,
+                },
+                solid: match matches.get_one::<String>("solid").map(String::as_str) {
+                    Some("directory") => Solid::Directory,
+                    Some("extension") => Solid::Extension,
+                    _ => Solid::None,
+                },
+                block_size: *matches
+                    .get_one::<usize>("block_size")
+                    .expect("Expected block size to have a default!"),
+                chunk_size: match matches.get_flag("chunking") {
+                    true => *matches
+                        .get_one::<u32>("chunk_size")
+                        .expect("Expected chunk size to have a default!") as usize,
+                    false => 0,
+                },
+                checksums: matches.get_flag("checksums"),
+                permissions: matches.get_flag("permissions"),
+                times: matches.get_flag("times"),
+                source_date_epoch: source_date_epoch()?,
+                symlinks: matches.get_flag("symlinks"),
+                include: matches
+                    .get_many::<String>("include")
+                    .map(|globs| globs.cloned().collect())
+                    .unwrap_or_default(),
+                exclude: matches
+                    .get_many::<String>("exclude")
+                    .map(|globs| globs.cloned().collect())
+                    .unwrap_or_default(),
+                password: match matches.get_flag("encrypt") || matches.get_flag("encrypt_header") {
+                    true => Some(password(&matches)?.ok_or(CatError::MissingPassword)?),
+                    false => None,
+                },
+                encrypt_header: matches.get_flag("encrypt_header"),
+                ..Default::default()
+            };
+
+            match matches.get_one::<String>("manifest") {
+                Some(manifest) => {
+                    let content = match manifest.as_str() {
+                        "-" => {
+                            let mut content = String::new();
+                            io::stdin().read_to_string(&mut content).map(|_| content)
+                        }
+                        path => fs::read_to_string(path),
+                    }
+                    .map_err(|err| CatError::FailedToOpenInput {
+                        path: manifest.clone(),
+                        error: err.to_string(),
+                    })?;
+                    let json = match matches.get_one::<String>("manifest_format").map(String::as_str) {
+                        Some(format) => format == "json",
+                        None => manifest.ends_with(".json"),
+                    };
+                    let manifest = match json {
+                        true => Manifest::from_json(&content)?,
+                        false => Manifest::from_text(&content)?,
+                    };
+                    pack_manifest(&manifest, archive_name, &context)
+                }
+                None => {
+                    let input = match matches.get_one::<String>("input_dir") {
+                        Some(file) => Path::new(file),
+                        None => Path::new("../.."),
+                    };
+                    pack(input, archive_name, &context)
+                }
+            }
+        }
         _ => CatError::UnknownArg.into(),
     }
 }
+
+/// The context for packing an archive converted from another format.
+fn conversion_context(command: &ArgMatches) -> Result<Context> {
+    Ok(Context {
+        verbose: command.get_flag("verbose"),
+        compression: match command.get_one::<String>("compression").map(String::as_str) {
+            Some("zstd") => Compression::Zstd,
+            Some("none") => Compression::None,
+            _ => Compression::Gzip,
+        },
+        level: command.get_one::<u32>("level").copied(),
+        jobs: *command
+            .get_one::<usize>("jobs")
+            .expect("Expected jobs to have a default!"),
+        permissions: command.get_flag("permissions"),
+        times: command.get_flag("times"),
+        source_date_epoch: source_date_epoch()?,
+        ..Default::default()
+    })
+}
+
+/// Reads `SOURCE_DATE_EPOCH`, the time modification times are clamped to for reproducible archives.
+fn source_date_epoch() -> Result<Option<i64>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => Ok(Some(epoch.trim().parse::<i64>().map_err(|_| {
+            CatError::InvalidEnvironmentVariable("SOURCE_DATE_EPOCH".to_string())
+        })?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads the password from the first line of the password file, or from `CATS_PASSWORD` if no file was given.
+fn password(matches: &ArgMatches) -> Result<Option<String>> {
+    match matches.get_one::<String>("password_file") {
+        Some(path) => {
+            let content = fs::read_to_string(path).map_err(|err| {
+                CatError::FailedToOpenInput {
+                    path: path.clone(),
+                    error: err.to_string(),
+                }
+            })?;
+            Ok(Some(content.lines().next().unwrap_or_default().to_string()))
+        }
+        None => Ok(env::var("CATS_PASSWORD").ok()),
+    }
+}
@@ -0,0 +1,141 @@
+use std::ops::Range;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use crate::error::{CatError, Result};
+use crate::metadata::{Encryption, SECTION_AUTHENTICATION};
+use crate::utils::EvalContext;
+
+const NONCE_LENGTH: usize = 24;
+const TAG_LENGTH: usize = 16;
+/// Where the authentication tag sits in a header stored in the clear, right after the version,
+/// the section count and the key and length of the first section.
+const AUTHENTICATION_OFFSET: usize = 7;
+/// Upper bounds for the Argon2id costs, the parameters come from the archive and an absurd memory
+/// cost would make opening it exhaust the machine.
+const MAX_MEMORY: u32 = 256 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u8 = 16;
+
+impl Encryption {
+    /// Fresh parameters with a random salt, using the OWASP recommended Argon2id costs.
+    pub fn generate() -> Encryption {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        Encryption {
+            salt,
+            memory: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// What an encrypted piece holds. It is bound to the piece along with its offset, so pieces can't
+/// be swapped or moved around without failing to decrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Piece {
+    Dictionary = 0x00,
+    Block = 0x01,
+    Chunk = 0x02,
+    File = 0x03,
+    SealedHeader = 0x04,
+    ClearHeader = 0x05,
+}
+
+impl Piece {
+    /// The associated data of the piece stored at the offset of the data section.
+    pub fn at(self, offset: u32) -> [u8; 5] {
+        let offset = offset.to_be_bytes();
+        [self as u8, offset[0], offset[1], offset[2], offset[3]]
+    }
+}
+
+/// XChaCha20-Poly1305 keyed from a password. Everything sealed with it carries its own random
+/// nonce, so any piece of the data section can be decrypted without touching the others.
+#[derive(Clone)]
+pub struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    /// How much larger sealing makes the content, the nonce in front and the tag behind it.
+    pub const OVERHEAD: usize = NONCE_LENGTH + TAG_LENGTH;
+
+    pub fn new(password: &str, encryption: &Encryption, context: &EvalContext) -> Result<Cipher> {
+        if encryption.memory > MAX_MEMORY
+            || encryption.iterations > MAX_ITERATIONS
+            || encryption.parallelism > MAX_PARALLELISM
+        {
+            return CatError::InvalidMetadata(context.clone()).into();
+        }
+        let params = Params::new(
+            encryption.memory,
+            encryption.iterations,
+            encryption.parallelism as u32,
+            Some(32),
+        )
+        .map_err(|_| CatError::InvalidMetadata(context.clone()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &encryption.salt, &mut key)
+            .map_err(|_| CatError::InvalidMetadata(context.clone()))?;
+
+        Ok(Cipher(XChaCha20Poly1305::new(&key.into())))
+    }
+
+    /// Encrypts the content, returning the nonce followed by the ciphertext and tag. The associated
+    /// data isn't stored, it has to be given again to open the content.
+    pub fn seal(&self, content: &[u8], associated: &[u8], context: &EvalContext) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .0
+            .encrypt(&nonce, Payload { msg: content, aad: associated })
+            .map_err(|err| CatError::UnableToEncrypt(context.clone(), err.to_string()))?;
+
+        let mut output = Vec::<u8>::with_capacity(NONCE_LENGTH + sealed.len());
+        output.extend_from_slice(&nonce);
+        output.extend(sealed);
+        Ok(output)
+    }
+
+    pub fn open(&self, content: &[u8], associated: &[u8], context: &EvalContext) -> Result<Vec<u8>> {
+        if content.len() < NONCE_LENGTH {
+            return CatError::UnableToDecrypt(context.clone()).into();
+        }
+
+        let (nonce, sealed) = content.split_at(NONCE_LENGTH);
+        self.0
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad: associated })
+            .map_err(|_| CatError::UnableToDecrypt(context.clone()))
+    }
+
+    /// Fills in the tag of a header stored in the clear, serialized starting at its version with
+    /// the authentication section first and zeroed. The tag covers every byte of the header.
+    pub fn authenticate(&self, header: &mut [u8], context: &EvalContext) -> Result<()> {
+        let range = authentication_range(header, context)?;
+        let associated = [&[Piece::ClearHeader as u8], &header[..]].concat();
+        let tag = self.seal(&[], &associated, context)?;
+        header[range].copy_from_slice(&tag);
+        Ok(())
+    }
+
+    /// Checks the tag of a header stored in the clear, as filled in by [Cipher::authenticate].
+    pub fn check(&self, header: &[u8], context: &EvalContext) -> Result<()> {
+        let range = authentication_range(header, context)?;
+        let mut associated = [&[Piece::ClearHeader as u8], header].concat();
+        associated[1 + range.start..1 + range.end].fill(0);
+        self.open(&header[range], &associated, context).map(|_| ())
+    }
+}
+
+/// Where the tag is in a serialized header, which has to start with the authentication section.
+fn authentication_range(header: &[u8], context: &EvalContext) -> Result<Range<usize>> {
+    let length = (Cipher::OVERHEAD as u32).to_be_bytes();
+    let end = AUTHENTICATION_OFFSET + Cipher::OVERHEAD;
+    match header.get(2..AUTHENTICATION_OFFSET) {
+        Some([SECTION_AUTHENTICATION, rest @ ..]) if rest == length && header.len() >= end => {
+            Ok(AUTHENTICATION_OFFSET..end)
+        }
+        _ => CatError::InvalidMetadata(context.clone()).into(),
+    }
+}
@@ -11,14 +11,44 @@ pub enum CatError {
         error: String,
     },
     InvalidFileType,
+    UnableToStartWorkers(String),
+    InvalidEnvironmentVariable(String),
+    InvalidPattern {
+        pattern: String,
+        error: String,
+    },
+    InvalidManifest {
+        entry: String,
+        error: String,
+    },
+    NoMatchingEntry(String),
+    EntryExists(String),
 
     FailedToCompressData(EvalContext, String),
     InvalidMetadata(EvalContext),
     UnknownVersion,
+    UnknownSection(EvalContext, u8),
+    InvalidCompressionLevel(u32),
+    InvalidKey {
+        path: String,
+        error: String,
+    },
+    MissingSignature,
+    InvalidSignature,
+    MissingPassword,
+    UnexpectedPassword,
 
     InvalidEntryName(EvalContext),
     InvalidEntryData(EvalContext),
     InvalidEntryType(EvalContext, u8),
+    UnknownAttribute(EvalContext, u8),
+    ChecksumMismatch {
+        context: EvalContext,
+        expected: u32,
+        actual: u32,
+    },
+    UnableToDecrypt(EvalContext),
+    UnableToEncrypt(EvalContext, String),
 
     UnableToCreateDirectory(String),
     ErrorWritingFile {
@@ -33,21 +63,38 @@ pub enum CatError {
     ErrorReadingMetadata(EvalContext, String),
 }
 
-impl Into<i32> for CatError {
-    fn into(self) -> i32 {
-        match self {
+impl From<CatError> for i32 {
+    fn from(val: CatError) -> Self {
+        match val {
             CatError::UnknownArg => -1,
             CatError::InvalidInput(_) => -1,
             CatError::FailedToOpenInput { .. } => -1,
             CatError::InvalidFileType => -1,
+            CatError::UnableToStartWorkers(_) => -3,
+            CatError::InvalidEnvironmentVariable(_) => -1,
+            CatError::InvalidPattern { .. } => -1,
+            CatError::InvalidManifest { .. } => -1,
+            CatError::NoMatchingEntry(_) => -1,
+            CatError::EntryExists(_) => -1,
 
             CatError::UnknownVersion => 1,
             CatError::InvalidMetadata { .. } => 2,
             CatError::FailedToCompressData { .. } => -2,
+            CatError::UnknownSection(_, _) => 3,
+            CatError::InvalidCompressionLevel(_) => -1,
+            CatError::InvalidKey { .. } => -1,
+            CatError::MissingSignature => 4,
+            CatError::InvalidSignature => 5,
+            CatError::MissingPassword => -1,
+            CatError::UnexpectedPassword => -1,
 
             CatError::InvalidEntryName(_) => 100,
             CatError::InvalidEntryData(_) => 101,
             CatError::InvalidEntryType(_, _) => 102,
+            CatError::UnknownAttribute(_, _) => 103,
+            CatError::ChecksumMismatch { .. } => 104,
+            CatError::UnableToDecrypt(_) => 105,
+            CatError::UnableToEncrypt(_, _) => 106,
 
             CatError::UnableToCreateDirectory(_) => 200,
             CatError::ErrorWritingFile { .. } => 201,
@@ -58,9 +105,9 @@ impl Into<i32> for CatError {
     }
 }
 
-impl<T> Into<std::result::Result<T, CatError>> for CatError {
-    fn into(self) -> std::result::Result<T, CatError> {
-        Err(self.into())
+impl<T> From<CatError> for std::result::Result<T, CatError> {
+    fn from(val: CatError) -> Self {
+        Err(val)
     }
 }
 
@@ -80,6 +127,37 @@ impl Display for CatError {
             }
             CatError::InvalidFileType => f.write_str("Invalid filetype"),
             CatError::UnknownArg => f.write_str("Unknown Argument"),
+            CatError::UnableToStartWorkers(error) => {
+                f.write_str("Unable to start worker threads reason: ")?;
+                f.write_str(error)
+            }
+            CatError::InvalidEnvironmentVariable(name) => {
+                f.write_str("Invalid value of environment variable '")?;
+                f.write_str(name)?;
+                f.write_str("'")
+            }
+            CatError::InvalidPattern { pattern, error } => {
+                f.write_str("Invalid pattern '")?;
+                f.write_str(pattern)?;
+                f.write_str("' reason: ")?;
+                f.write_str(error)
+            }
+            CatError::InvalidManifest { entry, error } => {
+                f.write_str("Invalid manifest entry '")?;
+                f.write_str(entry)?;
+                f.write_str("' reason: ")?;
+                f.write_str(error)
+            }
+            CatError::NoMatchingEntry(pattern) => {
+                f.write_str("No entry matches '")?;
+                f.write_str(pattern)?;
+                f.write_str("'")
+            }
+            CatError::EntryExists(path) => {
+                f.write_str("Entry '")?;
+                f.write_str(path)?;
+                f.write_str("' already exists")
+            }
 
             CatError::UnknownVersion => f.write_str("Unknown Version"),
             CatError::InvalidMetadata(context) => {
@@ -93,6 +171,27 @@ impl Display for CatError {
                 f.write_str("' reason: ")?;
                 f.write_str(error)
             }
+            CatError::UnknownSection(context, key) => {
+                f.write_str("Unknown section ")?;
+                u8::fmt(key, f)?;
+                f.write_str(" at '")?;
+                context.fmt(f)?;
+                f.write_str("'")
+            }
+            CatError::InvalidCompressionLevel(level) => {
+                f.write_str("Invalid compression level ")?;
+                u32::fmt(level, f)
+            }
+            CatError::InvalidKey { path, error } => {
+                f.write_str("Invalid key '")?;
+                f.write_str(path)?;
+                f.write_str("' reason: ")?;
+                f.write_str(error)
+            }
+            CatError::MissingSignature => f.write_str("Archive isn't signed"),
+            CatError::InvalidSignature => f.write_str("Invalid archive signature"),
+            CatError::MissingPassword => f.write_str("Archive is encrypted but no password was given"),
+            CatError::UnexpectedPassword => f.write_str("Archive isn't encrypted but a password was given"),
 
             CatError::InvalidEntryName(context) => {
                 f.write_str("Invalid filename at '")?;
@@ -111,6 +210,36 @@ impl Display for CatError {
                 context.fmt(f)?;
                 f.write_str("'")
             }
+            CatError::UnknownAttribute(context, key) => {
+                f.write_str("Unknown attribute ")?;
+                u8::fmt(key, f)?;
+                f.write_str(" at '")?;
+                context.fmt(f)?;
+                f.write_str("'")
+            }
+            CatError::ChecksumMismatch {
+                context,
+                expected,
+                actual,
+            } => {
+                f.write_str("Checksum mismatch at '")?;
+                context.fmt(f)?;
+                f.write_str("' expected ")?;
+                f.write_str(&format!("{expected:08x}"))?;
+                f.write_str(" but got ")?;
+                f.write_str(&format!("{actual:08x}"))
+            }
+            CatError::UnableToDecrypt(context) => {
+                f.write_str("Unable to decrypt '")?;
+                context.fmt(f)?;
+                f.write_str("', wrong password or corrupted data")
+            }
+            CatError::UnableToEncrypt(context, error) => {
+                f.write_str("Unable to encrypt '")?;
+                context.fmt(f)?;
+                f.write_str("' reason: ")?;
+                f.write_str(error)
+            }
 
             CatError::UnableToCreateDirectory(dir) => {
                 f.write_str("Unable to create directory '")?;
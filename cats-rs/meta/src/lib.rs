@@ -1,8 +1,84 @@
+pub mod crypto;
 pub mod error;
 pub mod metadata;
 pub mod utils;
 
+use crate::metadata::Compression;
+use ed25519_dalek::VerifyingKey;
+
+#[derive(Clone)]
 pub struct Context {
     pub verbose: bool,
-    pub gzip: bool,
+    pub compression: Compression,
+    /// Codec specific level, 1-9 for gzip and 1-22 for zstd. Uses the codec default when unset.
+    pub level: Option<u32>,
+    /// Number of worker threads, 0 picks one per available core.
+    pub jobs: usize,
+    /// Maximum size of a zstd dictionary trained from the packed files, none are trained when 0.
+    pub dictionary_size: usize,
+    pub solid: Solid,
+    /// Uncompressed size a solid block is filled up to.
+    pub block_size: usize,
+    /// Average size of the content defined chunks files are split into for dedup, files aren't
+    /// chunked when 0.
+    pub chunk_size: usize,
+    /// Stores a checksum of every file. Checksums need a version 2 header, which version 1 readers
+    /// such as cats4j refuse, so they are off by default. Encrypted archives only keep them if the
+    /// header is encrypted too.
+    pub checksums: bool,
+    /// Stores the Unix permissions of files and directories when packing, restores them when unpacking.
+    /// Like checksums they need a version 2 header, so they are only stored when asked for.
+    pub permissions: bool,
+    /// Stores the modification times of files and directories when packing, restores them when unpacking.
+    /// Like checksums they need a version 2 header, so they are only stored when asked for.
+    pub times: bool,
+    /// Modification times after this are clamped to it, see https://reproducible-builds.org/specs/source-date-epoch/
+    /// Archives without stored times don't depend on them in the first place.
+    pub source_date_epoch: Option<i64>,
+    /// Stores symbolic links as links instead of packing the files and directories they point to.
+    /// Links need a version 2 header, so they are followed unless asked for.
+    pub symlinks: bool,
+    /// Globs relative to the input directory, only matching files are packed when any are given.
+    pub include: Vec<String>,
+    /// Globs relative to the input directory that are never packed, even when included.
+    pub exclude: Vec<String>,
+    /// Archives have to be signed by this key to be unpacked.
+    pub verifying_key: Option<VerifyingKey>,
+    /// Encrypts the data when packing and decrypts it when unpacking.
+    pub password: Option<String>,
+    /// Encrypts the header too, so the names of the files aren't exposed.
+    pub encrypt_header: bool,
+}
+
+/// How files are grouped into solid blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Solid {
+    None,
+    Directory,
+    Extension,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            verbose: false,
+            compression: Compression::Gzip,
+            level: None,
+            jobs: 0,
+            dictionary_size: 0,
+            solid: Solid::None,
+            block_size: 4 * 1024 * 1024,
+            chunk_size: 0,
+            checksums: false,
+            permissions: false,
+            times: false,
+            source_date_epoch: None,
+            symlinks: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            verifying_key: None,
+            password: None,
+            encrypt_header: false,
+        }
+    }
 }
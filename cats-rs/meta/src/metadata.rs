@@ -1,27 +1,284 @@
+use std::fmt::{Display, Formatter};
+
 pub const MAGIC_NUMBER: [u8; 4] = [0x43, 0x41, 0x54, 0x53];
 
-#[derive(Debug)]
+/// Attribute and section keys below this value change how data has to be read, readers have to
+/// reject the ones they don't know. Keys at or above it are informational and can be skipped.
+pub const OPTIONAL_KEY: u8 = 0x80;
+
+pub const SECTION_DICTIONARIES: u8 = 0x00;
+pub const SECTION_BLOCKS: u8 = 0x01;
+pub const SECTION_CHUNKS: u8 = 0x02;
+pub const SECTION_ENCRYPTION: u8 = 0x03;
+pub const SECTION_SEALED_HEADER: u8 = 0x04;
+pub const SECTION_AUTHENTICATION: u8 = 0x05;
+
+pub const ENCRYPTION_ARGON2ID_XCHACHA20POLY1305: u8 = 0x00;
+
+pub const ATTRIBUTE_DICTIONARY: u8 = 0x00;
+pub const ATTRIBUTE_BLOCK: u8 = 0x01;
+pub const ATTRIBUTE_CHUNKS: u8 = 0x02;
+pub const ATTRIBUTE_CHECKSUM: u8 = 0x80;
+pub const ATTRIBUTE_MODE: u8 = 0x81;
+pub const ATTRIBUTE_MODIFIED: u8 = 0x82;
+
+/// Marks the signature trailer at the very end of a signed archive, "CSIG".
+pub const SIGNATURE_MAGIC: [u8; 4] = [0x43, 0x53, 0x49, 0x47];
+pub const SIGNATURE_LENGTH: usize = 32 + 64 + SIGNATURE_MAGIC.len();
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     pub version: u8,
+    pub dictionaries: Vec<Dictionary>,
+    pub blocks: Vec<Block>,
+    pub chunks: Vec<Chunk>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub encryption: Option<Encryption>,
+    /// The encrypted remainder of the header, only set until it has been decrypted.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub sealed: Option<Vec<u8>>,
+    /// The tag authenticating a header stored in the clear, see [crate::crypto::Cipher::authenticate].
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub authentication: Option<Vec<u8>>,
     pub entries: Vec<Entry>,
 }
 
+impl Header {
+    /// The lowest format version able to represent this header.
+    pub fn required_version(&self) -> u8 {
+        if self.dictionaries.is_empty()
+            && self.blocks.is_empty()
+            && self.chunks.is_empty()
+            && self.encryption.is_none()
+            && self.entries.iter().all(Entry::is_v1)
+        {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Where the last piece of the data section the header refers to ends. Whatever follows, like
+    /// a signature, isn't part of the content.
+    pub fn data_end(&self) -> u64 {
+        let pieces = self
+            .dictionaries
+            .iter()
+            .map(|dictionary| (dictionary.offset, dictionary.size))
+            .chain(self.blocks.iter().map(|block| (block.offset, block.size)))
+            .chain(self.chunks.iter().map(|chunk| (chunk.offset, chunk.size)));
+        let mut end = pieces.map(|(offset, size)| offset as u64 + size as u64).max().unwrap_or(0);
+        let mut entries = self.entries.iter().collect::<Vec<&Entry>>();
+        while let Some(entry) = entries.pop() {
+            match entry {
+                Entry::Directory { entries: children, .. } => entries.extend(children),
+                Entry::File {
+                    offset,
+                    size,
+                    attributes,
+                    ..
+                } if attributes.block.is_none() && attributes.chunks.is_none() => {
+                    end = end.max(*offset as u64 + *size as u64)
+                }
+                Entry::File { .. } | Entry::Symlink { .. } => {}
+            }
+        }
+        end
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "type", rename_all = "lowercase"))]
 pub enum Entry {
     Directory {
         name: String,
         entries: Vec<Entry>,
+        attributes: Attributes,
     },
     File {
         name: String,
         offset: u32,
         size: u32,
         compression: Compression,
+        attributes: Attributes,
+    },
+    /// A symbolic link, stored as the path it points to.
+    Symlink {
+        name: String,
+        target: String,
+        attributes: Attributes,
     },
 }
 
-#[derive(Debug, Clone)]
+impl Entry {
+    pub fn name(&self) -> &str {
+        match self {
+            Entry::Directory { name, .. } => name,
+            Entry::File { name, .. } => name,
+            Entry::Symlink { name, .. } => name,
+        }
+    }
+
+    fn is_v1(&self) -> bool {
+        match self {
+            Entry::Directory { entries, attributes, .. } => {
+                attributes.is_empty() && entries.iter().all(Entry::is_v1)
+            }
+            Entry::File {
+                compression,
+                attributes,
+                ..
+            } => compression.is_v1() && attributes.is_empty(),
+            Entry::Symlink { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
 pub enum Compression {
+    Zstd = 0xFD,
     Gzip = 0xFE,
     None = 0xFF,
 }
+
+impl Compression {
+    pub fn is_v1(&self) -> bool {
+        !matches!(self, Compression::Zstd)
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Zstd => f.write_str("zstd"),
+            Compression::Gzip => f.write_str("gzip"),
+            Compression::None => f.write_str("none"),
+        }
+    }
+}
+
+/// A zstd dictionary stored in the data section, shared by every file referencing its index.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dictionary {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Files compressed together, the files inside point into the decompressed block.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    pub offset: u32,
+    pub size: u32,
+    pub compression: Compression,
+}
+
+/// A piece of file content shared between files, produced by content defined chunking.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chunk {
+    pub offset: u32,
+    pub size: u32,
+    pub compression: Compression,
+}
+
+/// Password based encryption, every piece of the data section is encrypted on its own with a key
+/// derived from the password using Argon2id.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Encryption {
+    pub salt: [u8; 16],
+    /// Argon2id memory cost in KiB.
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct Attributes {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub dictionary: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub block: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub chunks: Option<Vec<u32>>,
+    /// CRC32C of the decompressed file content.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub checksum: Option<u32>,
+    /// Unix permission bits.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mode: Option<u16>,
+    /// Modification time in seconds since the Unix epoch.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub modified: Option<i64>,
+}
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        self.dictionary.is_none()
+            && self.block.is_none()
+            && self.chunks.is_none()
+            && self.checksum.is_none()
+            && self.mode.is_none()
+            && self.modified.is_none()
+    }
+}
+
+/// Ed25519 signature appended after the data section. It covers every byte in front of it, readers
+/// not knowing about it only see a few unreferenced bytes at the end of the data.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Signature {
+    pub public_key: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(with = "byte_array"))]
+    pub signature: [u8; 64],
+}
+
+impl Signature {
+    /// Splits an archive into the signed content and its signature, if it has one.
+    pub fn split(content: &[u8]) -> (&[u8], Option<Signature>) {
+        if content.len() < MAGIC_NUMBER.len() + SIGNATURE_LENGTH || !content.ends_with(&SIGNATURE_MAGIC) {
+            return (content, None);
+        }
+
+        let (signed, trailer) = content.split_at(content.len() - SIGNATURE_LENGTH);
+        let mut signature = Signature {
+            public_key: [0; 32],
+            signature: [0; 64],
+        };
+        signature.public_key.copy_from_slice(&trailer[..32]);
+        signature.signature.copy_from_slice(&trailer[32..96]);
+        (signed, Some(signature))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(SIGNATURE_LENGTH);
+        bytes.extend_from_slice(&self.public_key);
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&SIGNATURE_MAGIC);
+        bytes
+    }
+}
+
+/// Serde only covers arrays of up to 32 elements, longer ones go through a slice.
+#[cfg(feature = "serde")]
+mod byte_array {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(array: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(array)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        let length = bytes.len();
+        bytes
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(length, &"a byte array of the signature length"))
+    }
+}
@@ -3,12 +3,16 @@ use crate::error::{CatError, Result};
 use std::fmt::{Display, Formatter};
 use std::io::{Read, Write};
 
+/// Names are stored with a UByte length.
+pub const MAX_NAME_LENGTH: usize = u8::MAX as usize;
+
 pub fn validate_name(name: String, context: &EvalContext) -> Result<String> {
     if name
         .chars()
         .all(|c| c.is_ascii_graphic() && c != '/' && c != '\\')
         && name != ".."
-        && name.len() != 0
+        && !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
     {
         return Ok(name);
     }
@@ -40,22 +44,16 @@ impl Clone for EvalContext {
     fn clone(&self) -> Self {
         EvalContext {
             path: self.path.clone(),
-            parent: match &self.parent {
-                Some(parent) => Some(parent.clone()),
-                None => None
-            }
+            parent: self.parent.clone()
         }
     }
 }
 
 impl Display for EvalContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self.parent {
-            Some(parent) => {
-                Display::fmt(&parent, f)?;
-                f.write_str("/")?;
-            }
-            _ => {}
+        if let Some(parent) = &self.parent {
+            Display::fmt(&parent, f)?;
+            f.write_str("/")?;
         }
         f.write_str(&self.path)
     }
@@ -76,16 +74,33 @@ pub fn write_string(
     buffer: &mut impl Write,
 ) -> std::result::Result<(), std::io::Error> {
     let bytes = string.as_bytes();
-    let length = bytes.len();
-    buffer.write_all(&[length as u8])?;
+    let length = u8::try_from(bytes.len()).map_err(std::io::Error::other)?;
+    buffer.write_all(&[length])?;
+    buffer.write_all(bytes)
+}
+
+/// Writes a string with a UShort length, for values that can exceed the 255 bytes of a name.
+pub fn write_long_string(string: &str, buffer: &mut impl Write) -> std::result::Result<(), std::io::Error> {
+    let bytes = string.as_bytes();
+    let length = u16::try_from(bytes.len()).map_err(std::io::Error::other)?;
+    buffer.write_all(&u16::to_be_bytes(length))?;
     buffer.write_all(bytes)
 }
 
+pub fn read_long_string(buffer: &mut impl Read) -> std::result::Result<String, std::io::Error> {
+    let mut size = [0u8; 2];
+    buffer.read_exact(&mut size)?;
+    let size = u16::from_be_bytes(size);
+    let mut string = vec![0u8; size as usize];
+    buffer.read_exact(&mut string)?;
+    String::from_utf8(string).map_err(std::io::Error::other)
+}
+
 pub fn read_string(buffer: &mut impl Read) -> std::result::Result<String, std::io::Error> {
     let mut size = [0u8; 1];
     buffer.read_exact(&mut size)?;
-    let mut string = &mut Vec::with_capacity(size[0] as usize);
-    buffer.take(size[0] as u64).read_to_end(&mut string)?;
+    let string = &mut Vec::with_capacity(size[0] as usize);
+    buffer.take(size[0] as u64).read_to_end(string)?;
     Ok(String::from_utf8_lossy(string).to_string())
 }
 
@@ -101,6 +116,12 @@ pub fn read_u32(buffer: &mut impl Read) -> std::result::Result<u32, std::io::Err
     Ok(u32::from_be_bytes(number))
 }
 
+pub fn read_i64(buffer: &mut impl Read) -> std::result::Result<i64, std::io::Error> {
+    let mut number = [0u8; 8];
+    buffer.read_exact(&mut number)?;
+    Ok(i64::from_be_bytes(number))
+}
+
 pub fn read_u16(buffer: &mut impl Read) -> std::result::Result<u16, std::io::Error> {
     let mut number = [0u8; 2];
     buffer.read_exact(&mut number)?;
@@ -115,5 +136,42 @@ pub fn wrap_context<T, E>(
 where
     E: Error,
 {
-    result.map_err(|err| converter(context.clone(), err.to_string()).into())
+    result.map_err(|err| converter(context.clone(), err.to_string()))
+}
+
+/// Seconds since the Unix epoch of a UTC date and time in the proleptic Gregorian calendar.
+pub fn unix_time(year: i64, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> i64 {
+    // Days from civil, counting years from March so the leap day comes last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    days * 86400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64
+}
+
+/// The UTC year, month, day, hour, minute and second of seconds since the Unix epoch, the inverse
+/// of [unix_time].
+pub fn civil_time(time: i64) -> (i64, u8, u8, u8, u8, u8) {
+    let days = time.div_euclid(86400) + 719468;
+    let seconds = time.rem_euclid(86400);
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
 }
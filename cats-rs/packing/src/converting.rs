@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use meta::metadata::Attributes;
+use meta::utils::MAX_NAME_LENGTH;
+use meta::Context;
+use xxhash_rust::xxh3::xxh3_64;
+use crate::packing::{Content, Node};
+
+/// The archive layout read from a tar or zip archive, holding on to the file contents until
+/// everything is read.
+pub(crate) enum Tree {
+    Directory(BTreeMap<String, Tree>, Attributes),
+    File {
+        content: Content,
+        gzip: Option<Vec<u8>>,
+        attributes: Attributes,
+    },
+    Symlink(String),
+}
+
+/// Maps the path of an entry, reporting when it had to be renamed or is skipped.
+pub(crate) fn map_entry(raw: &str, context: &Context) -> Option<Vec<String>> {
+    let Some(names) = map_path(raw) else {
+        eprintln!("Skipping {raw} as it leaves the archive");
+        return None;
+    };
+    if names.is_empty() {
+        return None;
+    }
+    let path = names.join("/");
+    if path != raw.trim_start_matches("./").trim_matches('/') {
+        eprintln!("Renaming {raw} to {path}");
+    }
+    if context.verbose {
+        println!("Reading {path}");
+    }
+    Some(names)
+}
+
+/// Splits a path into names CATS allows. Leading `/` and `.` components are dropped like tar and
+/// zip tools do, characters `validate_name` rejects are replaced with `_` and over-long names are
+/// shortened. Paths going up with `..` are refused.
+pub(crate) fn map_path(path: &str) -> Option<Vec<String>> {
+    let mut names = Vec::<String>::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => return None,
+            name => names.push(map_name(name)),
+        }
+    }
+    Some(names)
+}
+
+/// Maps the names in a link target like [map_path] does, so links to renamed entries still resolve.
+pub(crate) fn map_target(target: &str) -> String {
+    target
+        .split('/')
+        .map(|name| match name {
+            "" | "." | ".." => name.to_string(),
+            name => map_name(name),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Replaces the characters CATS doesn't allow in names. Names longer than CATS allows are cut
+/// short and end in a hash of the full name instead, so different long names stay different.
+fn map_name(name: &str) -> String {
+    let mapped = name
+        .chars()
+        .map(|c| match c.is_ascii_graphic() && c != '\\' {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>();
+    if mapped.len() <= MAX_NAME_LENGTH {
+        return mapped;
+    }
+
+    let suffix = format!("~{:016x}", xxh3_64(name.as_bytes()));
+    // Only ASCII is left, so any byte is a character boundary.
+    format!("{}{suffix}", &mapped[..MAX_NAME_LENGTH - suffix.len()])
+}
+
+pub(crate) fn find<'a>(root: &'a BTreeMap<String, Tree>, names: &[String]) -> Option<&'a Tree> {
+    let (name, parents) = names.split_last()?;
+    let mut directory = root;
+    for parent in parents {
+        directory = match directory.get(parent)? {
+            Tree::Directory(children, _) => children,
+            _ => return None,
+        };
+    }
+    directory.get(name)
+}
+
+/// Inserts an entry, creating the directories leading up to it. Later entries replace earlier
+/// ones like they do when extracting, a directory only updates the attributes of an
+/// existing one.
+pub(crate) fn insert(root: &mut BTreeMap<String, Tree>, names: &[String], tree: Tree) {
+    let Some((name, parents)) = names.split_last() else {
+        return;
+    };
+    let mut directory = root;
+    for (index, parent) in parents.iter().enumerate() {
+        let entry = directory
+            .entry(parent.clone())
+            .or_insert_with(|| Tree::Directory(BTreeMap::new(), Attributes::default()));
+        if !matches!(entry, Tree::Directory(..)) {
+            eprintln!("Replacing {} with a directory", names[..=index].join("/"));
+            *entry = Tree::Directory(BTreeMap::new(), Attributes::default());
+        }
+        let Tree::Directory(children, _) = entry else {
+            unreachable!()
+        };
+        directory = children;
+    }
+
+    match (directory.get_mut(name), tree) {
+        (Some(Tree::Directory(_, existing)), Tree::Directory(_, attributes)) => *existing = attributes,
+        (existing, tree) => {
+            if existing.is_some() {
+                eprintln!("Replacing {} with a later entry of the same name", names.join("/"));
+            }
+            directory.insert(name.clone(), tree);
+        }
+    }
+}
+
+pub(crate) fn create_nodes(tree: BTreeMap<String, Tree>, parent: &Path) -> Vec<Node> {
+    tree.into_iter()
+        .map(|(name, tree)| {
+            let path = parent.join(&name);
+            match tree {
+                Tree::Directory(children, attributes) => Node::Directory {
+                    children: create_nodes(children, &path),
+                    name,
+                    attributes,
+                },
+                Tree::File {
+                    content,
+                    gzip,
+                    attributes,
+                } => Node::File {
+                    name,
+                    path,
+                    content,
+                    gzip,
+                    attributes,
+                },
+                Tree::Symlink(target) => Node::Symlink { name, target },
+            }
+        })
+        .collect()
+}
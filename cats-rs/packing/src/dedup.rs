@@ -0,0 +1,72 @@
+use rayon::prelude::*;
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Returns the index of the first identical item for every item, identified by their size and
+/// hash. Items without a hash are unique, `equal` compares the bytes of two items whose keys match.
+pub(crate) fn dedup_keys<E>(
+    keys: &[(usize, Option<u64>)],
+    equal: impl Fn(usize, usize) -> Result<bool, E>,
+) -> Result<Vec<usize>, E> {
+    let mut candidates = HashMap::<(usize, u64), Vec<usize>>::new();
+    let mut firsts = Vec::<usize>::with_capacity(keys.len());
+    for (index, (size, hash)) in keys.iter().enumerate() {
+        let Some(hash) = hash else {
+            firsts.push(index);
+            continue;
+        };
+
+        let candidates = candidates.entry((*size, *hash)).or_default();
+        let mut first = None;
+        for candidate in candidates.iter() {
+            if equal(*candidate, index)? {
+                first = Some(*candidate);
+                break;
+            }
+        }
+        match first {
+            Some(candidate) => firsts.push(candidate),
+            None => {
+                candidates.push(index);
+                firsts.push(index);
+            }
+        }
+    }
+    Ok(firsts)
+}
+
+/// Like [dedup_keys] for items read on demand. Items are bucketed by size, `size` tells it if it
+/// is known without reading the item. Only items whose size isn't known or is shared with another
+/// one are read to hash them, and read again to compare them when their hashes match.
+pub(crate) fn dedup_lazy<E: Send>(
+    count: usize,
+    size: impl Fn(usize) -> Result<Option<u64>, E> + Sync,
+    load: impl Fn(usize) -> Result<Vec<u8>, E> + Sync,
+) -> Result<Vec<usize>, E> {
+    let hash = |index: usize| {
+        let item = load(index)?;
+        Ok((item.len(), Some(xxh3_64(&item))))
+    };
+    let known = (0..count)
+        .into_par_iter()
+        .map(|index| match size(index)? {
+            Some(size) => Ok((size as usize, None)),
+            None => hash(index),
+        })
+        .collect::<Result<Vec<(usize, Option<u64>)>, E>>()?;
+    let mut sizes = HashMap::<usize, u32>::new();
+    for (size, _) in &known {
+        *sizes.entry(*size).or_default() += 1;
+    }
+
+    let keys = known
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, (size, hash_of))| match hash_of {
+            None if sizes[&size] > 1 => hash(index),
+            key => Ok((size, key)),
+        })
+        .collect::<Result<Vec<(usize, Option<u64>)>, E>>()?;
+
+    dedup_keys(&keys, |a, b| Ok(load(a)? == load(b)?))
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use meta::error::CatError;
+use meta::metadata::{Header, MAGIC_NUMBER};
+use meta::utils::EvalContext;
+use meta::Context;
+use unpacking::layout::data_regions;
+use unpacking::reader::{split_header, CatReader};
+use unpacking::signature::is_signed;
+use crate::serializing::CatSerializable;
+
+/// Describes a dumped archive, the header as stored followed by the data section in pieces.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Dump {
+    header: Header,
+    /// The file holding the encrypted remainder of a sealed header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed: Option<PathBuf>,
+    data: Vec<Blob>,
+}
+
+/// A piece of the data section. Only the file is read back, the offset, size and contents are
+/// there to find one's way around.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Blob {
+    file: PathBuf,
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    size: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    contents: Vec<String>,
+}
+
+const DUMP_FILE: &str = "dump.json";
+const SEALED_FILE: &str = "sealed.bin";
+const DATA_DIRECTORY: &str = "data";
+
+/// Writes the header of the archive to `dump.json` in the directory and the data section to raw
+/// files next to it, cut wherever a file, dictionary, block, chunk or the signature starts or ends.
+/// Nothing is decoded, a sealed header is written as is and only opened with the password to tell
+/// what the pieces of the data section hold.
+pub fn dump(archive: &Path, directory: &Path, context: &Context) -> meta::error::Result<()> {
+    let content = fs::read(archive).map_err(|err| CatError::FailedToOpenInput {
+        path: archive.display().to_string(),
+        error: err.to_string(),
+    })?;
+    let (mut header, data) = split_header(&content)?;
+
+    let mut stored = Vec::<u8>::new();
+    header.serialize(&mut stored, header.version, EvalContext::new("dump".to_string()))?;
+    if content[MAGIC_NUMBER.len()..content.len() - data.len()] != stored[..] {
+        eprintln!(
+            "The header of {} holds unknown sections or attributes or orders them differently, assembling the dump won't reproduce it byte for byte",
+            archive.display()
+        );
+    }
+
+    let known = match (&header.sealed, &context.password) {
+        (Some(_), Some(_)) => Some(CatReader::from_bytes_with(content.clone(), context)?.header().clone()),
+        (Some(_), None) => None,
+        (None, _) => Some(header.clone()),
+    };
+
+    let data_directory = directory.join(DATA_DIRECTORY);
+    fs::create_dir_all(&data_directory)
+        .map_err(|_| CatError::UnableToCreateDirectory(data_directory.display().to_string()))?;
+    let mut blobs = Vec::<Blob>::new();
+    for (i, region) in data_regions(known.as_ref(), data, is_signed(&content)).into_iter().enumerate() {
+        let file = Path::new(DATA_DIRECTORY).join(format!("{i:05}.bin"));
+        write_file(directory, &file, &data[region.offset..region.offset + region.size], context)?;
+        blobs.push(Blob {
+            file,
+            offset: region.offset,
+            size: region.size,
+            contents: region.contents,
+        });
+    }
+
+    let sealed = match header.sealed.take() {
+        Some(sealed) => {
+            write_file(directory, Path::new(SEALED_FILE), &sealed, context)?;
+            Some(PathBuf::from(SEALED_FILE))
+        }
+        None => None,
+    };
+    let dump = Dump {
+        header,
+        sealed,
+        data: blobs,
+    };
+    let json = serde_json::to_string_pretty(&dump).map_err(|err| CatError::ErrorWritingFile {
+        path: DUMP_FILE.to_string(),
+        error: err.to_string(),
+    })?;
+    write_file(directory, Path::new(DUMP_FILE), json.as_bytes(), context)
+}
+
+/// Builds an archive from a directory written by [`dump`], the header from `dump.json` followed by
+/// the listed pieces of the data section in order. Nothing is checked or recomputed, so the header
+/// can point anywhere and the version can be any byte.
+pub fn assemble(directory: &Path, target: &Path, context: &Context) -> meta::error::Result<()> {
+    let dump_file = directory.join(DUMP_FILE);
+    let json = fs::read_to_string(&dump_file).map_err(|err| CatError::FailedToOpenInput {
+        path: dump_file.display().to_string(),
+        error: err.to_string(),
+    })?;
+    let dump = serde_json::from_str::<Dump>(&json).map_err(|err| CatError::InvalidManifest {
+        entry: format!("line {}", err.line()),
+        error: err.to_string(),
+    })?;
+
+    let mut header = dump.header;
+    if let Some(sealed) = &dump.sealed {
+        header.sealed = Some(read_file(directory, sealed, context)?);
+    }
+    let mut content = MAGIC_NUMBER.to_vec();
+    header.serialize(&mut content, header.version, EvalContext::new("assemble".to_string()))?;
+    for blob in &dump.data {
+        content.extend(read_file(directory, &blob.file, context)?);
+    }
+
+    fs::write(target, content).map_err(|err| CatError::ErrorWritingFile {
+        path: target.display().to_string(),
+        error: err.to_string(),
+    })
+}
+
+fn write_file(directory: &Path, file: &Path, content: &[u8], context: &Context) -> meta::error::Result<()> {
+    if context.verbose {
+        println!("Writing {}", file.display());
+    }
+    let path = directory.join(file);
+    fs::write(&path, content).map_err(|err| CatError::ErrorWritingFile {
+        path: path.display().to_string(),
+        error: err.to_string(),
+    })
+}
+
+fn read_file(directory: &Path, file: &Path, context: &Context) -> meta::error::Result<Vec<u8>> {
+    if context.verbose {
+        println!("Reading {}", file.display());
+    }
+    let path = directory.join(file);
+    fs::read(&path).map_err(|err| CatError::ErrorReadingFile {
+        path: path.display().to_string(),
+        error: err.to_string(),
+    })
+}
@@ -0,0 +1,739 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use meta::crypto::{Cipher, Piece};
+use meta::error::CatError;
+use meta::metadata::{
+    Attributes, Block, Chunk, Compression, Dictionary, Encryption, Entry, Header, SIGNATURE_LENGTH, SIGNATURE_MAGIC,
+};
+use meta::utils::{validate_name, EvalContext};
+use meta::Context;
+use globset::{GlobBuilder, GlobMatcher};
+use unpacking::reader::CatReader;
+use crate::dedup::dedup_lazy;
+use crate::manifest::Manifest;
+use crate::packing::{create_pool, manifest_nodes, serialize_tree, write, write_error, Base, EntryData};
+
+/// Appends the files and directories listed in the manifest to an existing archive. The new blobs
+/// go after the current data section, so nothing already stored moves, files identical to a stored
+/// one point to it instead. Entries already present at the same path are replaced, directories are
+/// merged. Dictionaries and chunking aren't used for the appended files.
+pub fn add(archive: &Path, manifest: &Manifest, context: &Context) -> meta::error::Result<()> {
+    let (reader, size) = open(archive, context)?;
+    if reader.cipher().is_none() && context.password.is_some() {
+        return CatError::UnexpectedPassword.into();
+    }
+    let pool = create_pool(context)?;
+    let context = Context {
+        dictionary_size: 0,
+        chunk_size: 0,
+        ..context.clone()
+    };
+
+    let (header, data, cipher) = pool.install(|| {
+        let mut existing = Vec::<(&Entry, EntryData)>::new();
+        collect_blobs(&reader.header().entries, &mut HashSet::new(), &mut existing);
+        let base = Base {
+            size: usize::try_from(size).expect("Failed to convert u64 to usize"),
+            dictionaries: reader.header().dictionaries.clone(),
+            blocks: reader.header().blocks.clone(),
+            chunks: reader.header().chunks.clone(),
+            encryption: reader.header().encryption.clone().zip(reader.cipher().cloned()),
+            reader: Some(&reader),
+            existing,
+        };
+        serialize_tree(manifest_nodes(manifest, archive, &context)?, base, &context)
+    })?;
+
+    let mut entries = reader.header().entries.clone();
+    merge_entries(
+        &mut entries,
+        header.entries,
+        Conflict::Last,
+        &context,
+        "",
+    )?;
+    let header = Header { entries, ..header };
+    write(archive, header, cipher.as_ref(), reader.is_sealed(), |output| {
+        reader.copy_data(size, output).map_err(write_error(archive))?;
+        output.write_all(&data).map_err(write_error(archive))
+    })
+}
+
+/// Removes every entry whose `/` separated path matches one of the globs, directories with
+/// everything below them. The data stays where it is unless `compact` is set, which rewrites the
+/// data section with only what the remaining entries still reference.
+pub fn remove(archive: &Path, patterns: &[String], compact: bool, context: &Context) -> meta::error::Result<()> {
+    let matchers = patterns
+        .iter()
+        .map(|pattern| {
+            GlobBuilder::new(pattern.trim_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map(|glob| glob.compile_matcher())
+                .map_err(|err| CatError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    error: err.kind().to_string(),
+                })
+        })
+        .collect::<meta::error::Result<Vec<GlobMatcher>>>()?;
+
+    let (reader, size) = open(archive, context)?;
+    let mut header = reader.header().clone();
+    // Every pattern is matched against the whole tree first, one may match below another.
+    let mut matched = vec![false; matchers.len()];
+    match_entries(&header.entries, "", &matchers, &mut matched);
+    if let Some(index) = matched.iter().position(|matched| !matched) {
+        return CatError::NoMatchingEntry(patterns[index].clone()).into();
+    }
+    remove_entries(&mut header.entries, "", &matchers, context);
+
+    if !compact {
+        return write(archive, header, reader.cipher(), reader.is_sealed(), |output| {
+            reader.copy_data(size, output).map_err(write_error(archive))
+        });
+    }
+    let mut layout = Layout::default();
+    let recrypt = Recrypt {
+        from: reader.cipher(),
+        to: reader.cipher(),
+    };
+    layout.relocate(&mut header.entries, reader.header(), Pieces::Archive(&reader, recrypt))?;
+    header.dictionaries = layout.dictionaries.clone();
+    header.blocks = layout.blocks.clone();
+    header.chunks = layout.chunks.clone();
+    write(archive, header, reader.cipher(), reader.is_sealed(), |output| layout.write(output, archive))
+}
+
+/// Marks the patterns matching any path of the tree.
+fn match_entries(entries: &[Entry], parent: &str, matchers: &[GlobMatcher], matched: &mut [bool]) {
+    for entry in entries {
+        let path = match parent {
+            "" => entry.name().to_string(),
+            parent => format!("{parent}/{}", entry.name()),
+        };
+        for (matcher, matched) in matchers.iter().zip(matched.iter_mut()) {
+            *matched |= matcher.is_match(&path);
+        }
+        if let Entry::Directory { entries, .. } = entry {
+            match_entries(entries, &path, matchers, matched);
+        }
+    }
+}
+
+fn remove_entries(entries: &mut Vec<Entry>, parent: &str, matchers: &[GlobMatcher], context: &Context) {
+    entries.retain_mut(|entry| {
+        let path = match parent {
+            "" => entry.name().to_string(),
+            parent => format!("{parent}/{}", entry.name()),
+        };
+        if matchers.iter().any(|matcher| matcher.is_match(&path)) {
+            if context.verbose {
+                println!("Removing {path}");
+            }
+            return false;
+        }
+
+        if let Entry::Directory { entries, .. } = entry {
+            remove_entries(entries, &path, matchers, context);
+        }
+        true
+    });
+}
+
+/// Moves the entry at `from` to `to`, creating the directories leading up to it. Like mv(1), an
+/// entry moved onto an existing directory goes into it under its own name. Only the header
+/// changes, the data section is written back byte for byte.
+pub fn rename(archive: &Path, from: &str, to: &str, context: &Context) -> meta::error::Result<()> {
+    let eval_context = EvalContext::new(to.to_string());
+    let source = from.split('/').filter(|name| !name.is_empty()).collect::<Vec<&str>>();
+    let mut target = to
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .map(|name| validate_name(name.to_string(), &eval_context))
+        .collect::<meta::error::Result<Vec<String>>>()?;
+    if target.is_empty() {
+        return CatError::InvalidEntryName(eval_context).into();
+    }
+    if target.len() > source.len() && target.iter().zip(&source).all(|(a, b)| a == b) {
+        return CatError::InvalidInput(to.to_string()).into();
+    }
+
+    let (reader, size) = open(archive, context)?;
+    let mut header = reader.header().clone();
+    let Some(mut entry) = take_entry(&mut header.entries, &source) else {
+        return CatError::NoMatchingEntry(from.to_string()).into();
+    };
+    if let Some(Entry::Directory { .. }) = find_entry(&header.entries, &target) {
+        target.push(entry.name().to_string());
+    }
+    if context.verbose {
+        println!("Moving {from} to {}", target.join("/"));
+    }
+
+    let (name, parents) = target.split_last().expect("Expected a target name");
+    let mut entries = &mut header.entries;
+    for parent in parents {
+        let index = match entries.iter().position(|entry| entry.name() == parent) {
+            Some(index) => index,
+            None => {
+                entries.push(Entry::Directory {
+                    name: parent.clone(),
+                    entries: Vec::new(),
+                    attributes: Attributes::default(),
+                });
+                entries.sort_by(|a, b| a.name().cmp(b.name()));
+                entries.iter().position(|entry| entry.name() == parent).unwrap()
+            }
+        };
+        entries = match &mut entries[index] {
+            Entry::Directory { entries, .. } => entries,
+            _ => return CatError::EntryExists(to.to_string()).into(),
+        };
+    }
+    if entries.iter().any(|entry| entry.name() == name) {
+        return CatError::EntryExists(target.join("/")).into();
+    }
+    match &mut entry {
+        Entry::Directory { name: old, .. } | Entry::File { name: old, .. } | Entry::Symlink { name: old, .. } => {
+            *old = name.clone()
+        }
+    }
+    entries.push(entry);
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+
+    write(archive, header, reader.cipher(), reader.is_sealed(), |output| {
+        reader.copy_data(size, output).map_err(write_error(archive))
+    })
+}
+
+fn find_entry<'a>(entries: &'a [Entry], path: &[String]) -> Option<&'a Entry> {
+    let (name, rest) = path.split_first()?;
+    let entry = entries.iter().find(|entry| entry.name() == name)?;
+    match entry {
+        _ if rest.is_empty() => Some(entry),
+        Entry::Directory { entries, .. } => find_entry(entries, rest),
+        _ => None,
+    }
+}
+
+/// Detaches the entry at the path from the tree.
+fn take_entry(entries: &mut Vec<Entry>, path: &[&str]) -> Option<Entry> {
+    let (name, rest) = path.split_first()?;
+    let index = entries.iter().position(|entry| entry.name() == *name)?;
+    if rest.is_empty() {
+        return Some(entries.remove(index));
+    }
+    match &mut entries[index] {
+        Entry::Directory { entries, .. } => take_entry(entries, rest),
+        _ => None,
+    }
+}
+
+/// How an entry present in more than one archive is resolved when merging them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keeps the entry of the archive listed first.
+    First,
+    /// Keeps the entry of the archive listed last.
+    Last,
+    /// Refuses to merge.
+    Error,
+}
+
+/// Merges archives into a new one, directories present in several of them are merged recursively.
+/// Stored data is copied as it is without recompressing it, and identical files are only kept
+/// once. The result is encrypted if any of the archives is, using the password of the context.
+pub fn merge(target: &Path, sources: &[PathBuf], conflict: Conflict, context: &Context) -> meta::error::Result<()> {
+    let readers = sources
+        .iter()
+        .map(|source| CatReader::open_with(source, context))
+        .collect::<meta::error::Result<Vec<CatReader>>>()?;
+
+    let sealed = context.encrypt_header || readers.iter().any(CatReader::is_sealed);
+    let encryption = match sealed || readers.iter().any(|reader| reader.cipher().is_some()) {
+        true => {
+            let password = context.password.as_ref().ok_or(CatError::MissingPassword)?;
+            let encryption = Encryption::generate();
+            let cipher = Cipher::new(password, &encryption, &EvalContext::new("encryption".to_string()))?;
+            Some((encryption, cipher))
+        }
+        false => None,
+    };
+    let cipher = encryption.as_ref().map(|(_, cipher)| cipher);
+
+    // Every archive is laid out as a whole first, which only plans where its pieces go. Identical
+    // files are then pointed to the same copy, and laying that out again leaves out whatever isn't
+    // referenced anymore. Pieces are only read once the result is written.
+    let pool = create_pool(context)?;
+    let (entries, layout) = pool.install(|| -> meta::error::Result<(Vec<Entry>, Layout)> {
+        let mut merged = Layout::default();
+        let mut entries = Vec::<Entry>::new();
+        let mut blobs = Vec::<(&CatReader, &Entry)>::new();
+        let mut locations = Vec::<Location>::new();
+        for (reader, source) in readers.iter().zip(sources) {
+            if context.verbose {
+                println!("Merging {}", source.display());
+            }
+            let mut tree = reader.header().entries.clone();
+            let recrypt = Recrypt {
+                from: reader.cipher(),
+                to: cipher,
+            };
+            merged.relocate(&mut tree, reader.header(), Pieces::Archive(reader, recrypt))?;
+
+            // Relocating keeps distinct blobs distinct, so they are still found in the same order.
+            let mut stored = Vec::<(&Entry, EntryData)>::new();
+            collect_blobs(&reader.header().entries, &mut HashSet::new(), &mut stored);
+            let mut relocated = Vec::<(&Entry, EntryData)>::new();
+            collect_blobs(&tree, &mut HashSet::new(), &mut relocated);
+            for ((entry, _), (_, data)) in stored.into_iter().zip(relocated) {
+                blobs.push((reader, entry));
+                locations.push(location(&data));
+            }
+            merge_entries(&mut entries, tree, conflict, context, "")?;
+        }
+
+        let eval_context = |index: usize| EvalContext::new(blobs[index].1.name().to_string());
+        let firsts = dedup_lazy(
+            blobs.len(),
+            |index| blobs[index].0.content_size(blobs[index].1, &eval_context(index)),
+            |index| blobs[index].0.read_entry(blobs[index].1, &eval_context(index)),
+        )?;
+        let duplicates = locations
+            .iter()
+            .zip(firsts)
+            .filter(|(blob, first)| *blob != &locations[*first])
+            .map(|(blob, first)| (blob.clone(), locations[first].clone()))
+            .collect::<HashMap<Location, Location>>();
+        point_to_duplicates(&mut entries, &duplicates);
+
+        let source = Header {
+            version: 2,
+            dictionaries: std::mem::take(&mut merged.dictionaries),
+            blocks: std::mem::take(&mut merged.blocks),
+            chunks: std::mem::take(&mut merged.chunks),
+            encryption: None,
+            sealed: None,
+            authentication: None,
+            entries: Vec::new(),
+        };
+        let mut layout = Layout::default();
+        layout.relocate(&mut entries, &source, Pieces::Layout(&merged, cipher))?;
+        Ok((entries, layout))
+    })?;
+
+    let header = Header {
+        version: 1,
+        dictionaries: layout.dictionaries.clone(),
+        blocks: layout.blocks.clone(),
+        chunks: layout.chunks.clone(),
+        encryption: encryption.as_ref().map(|(encryption, _)| encryption.clone()),
+        sealed: None,
+        authentication: None,
+        entries,
+    };
+    write(target, header, cipher, sealed, |output| layout.write(output, target))
+}
+
+/// Where and how the content of a file is stored, the parts of [Entry::File] blobs are shared by.
+type Location = (u32, u32, Compression, Attributes);
+
+fn location(data: &EntryData) -> Location {
+    (data.offset, data.size, data.compression.clone(), data.attributes.clone())
+}
+
+/// Points every file stored at one of the keys to the location it maps to instead.
+fn point_to_duplicates(entries: &mut [Entry], duplicates: &HashMap<Location, Location>) {
+    for entry in entries {
+        match entry {
+            Entry::Directory { entries, .. } => point_to_duplicates(entries, duplicates),
+            Entry::File {
+                offset,
+                size,
+                compression,
+                attributes,
+                ..
+            } => {
+                let key = (
+                    *offset,
+                    *size,
+                    compression.clone(),
+                    Attributes {
+                        mode: None,
+                        modified: None,
+                        ..attributes.clone()
+                    },
+                );
+                if let Some((new_offset, new_size, new_compression, new_attributes)) = duplicates.get(&key) {
+                    *offset = *new_offset;
+                    *size = *new_size;
+                    *compression = new_compression.clone();
+                    // The content is the same, so a checksum only the entry has holds for the copy.
+                    *attributes = Attributes {
+                        mode: attributes.mode,
+                        modified: attributes.modified,
+                        checksum: new_attributes.checksum.or(attributes.checksum),
+                        ..new_attributes.clone()
+                    };
+                }
+            }
+            Entry::Symlink { .. } => {}
+        }
+    }
+}
+
+/// A data section being rebuilt from the pieces of existing archives. Relocating only plans where
+/// every piece goes, the pieces are read from their archives when the layout is written. They are
+/// copied as they are stored, so nothing is recompressed.
+#[derive(Default)]
+pub(crate) struct Layout<'a> {
+    size: u32,
+    transfers: Vec<Transfer<'a>>,
+    /// The transfer placed at each offset, so a later layout can take pieces from this one.
+    placed: HashMap<u32, usize>,
+    pub(crate) dictionaries: Vec<Dictionary>,
+    pub(crate) blocks: Vec<Block>,
+    pub(crate) chunks: Vec<Chunk>,
+}
+
+impl<'a> Layout<'a> {
+    /// Plans copying everything the entries reference out of the data section of `source`, and
+    /// points the entries to the copies. A piece shared by several entries is only copied once.
+    pub(crate) fn relocate(
+        &mut self,
+        entries: &mut [Entry],
+        source: &Header,
+        pieces: Pieces<'_, 'a>,
+    ) -> meta::error::Result<()> {
+        let mut relocation = Relocation {
+            source,
+            pieces,
+            ranges: HashMap::new(),
+            dictionaries: HashMap::new(),
+            blocks: HashMap::new(),
+            chunks: HashMap::new(),
+        };
+        relocation.entries(self, entries, &EvalContext::new("relocating".to_string()))
+    }
+
+    /// Plans appending a piece and returns its new offset and size.
+    fn copy(
+        &mut self,
+        pieces: &Pieces<'_, 'a>,
+        piece: Piece,
+        offset: u32,
+        size: u32,
+        context: &EvalContext,
+    ) -> meta::error::Result<(u32, u32)> {
+        let transfer = match pieces {
+            Pieces::Archive(reader, recrypt) => Transfer {
+                reader,
+                piece,
+                offset,
+                size,
+                recrypt: *recrypt,
+                relocated: self.size,
+                context: context.clone(),
+            },
+            Pieces::Layout(layout, to) => match layout.placed.get(&offset).map(|index| &layout.transfers[*index]) {
+                Some(transfer) if transfer.piece == piece && transfer.relocated_size() == Some(size) => Transfer {
+                    recrypt: Recrypt {
+                        from: transfer.recrypt.from,
+                        to: *to,
+                    },
+                    relocated: self.size,
+                    ..transfer.clone()
+                },
+                _ => return CatError::InvalidEntryData(context.clone()).into(),
+            },
+        };
+        let Some(size) = transfer.relocated_size() else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+
+        let relocated = self.size;
+        self.size = u32::try_from(relocated as u64 + size as u64).expect("Failed to convert u64 to u32");
+        self.placed.insert(relocated, self.transfers.len());
+        self.transfers.push(transfer);
+        Ok((relocated, size))
+    }
+
+    /// Reads every piece from its archive and writes it to `output`, in order. Encrypted pieces
+    /// are bound to their offset, so one that moves is sealed again even if the cipher stays the
+    /// same.
+    pub(crate) fn write(&self, output: &mut dyn Write, target: &Path) -> meta::error::Result<()> {
+        for transfer in &self.transfers {
+            let context = &transfer.context;
+            let content = transfer.reader.read_raw(transfer.offset, transfer.size, context)?;
+            let (stored, relocated) = (transfer.piece.at(transfer.offset), transfer.piece.at(transfer.relocated));
+            let content = match transfer.recrypt {
+                Recrypt {
+                    from: Some(from),
+                    to: Some(to),
+                } if std::ptr::eq(from, to) && transfer.offset == transfer.relocated => content,
+                Recrypt { from, to } => {
+                    let content = match from {
+                        Some(cipher) => Cow::Owned(cipher.open(&content, &stored, context)?),
+                        None => content,
+                    };
+                    match to {
+                        Some(cipher) => Cow::Owned(cipher.seal(&content, &relocated, context)?),
+                        None => content,
+                    }
+                }
+            };
+            output.write_all(&content).map_err(write_error(target))?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves pieces from one encryption to another, either side may be unencrypted. Pieces of an
+/// encrypted archive that move need one, even if they stay with the same cipher.
+#[derive(Clone, Copy)]
+pub(crate) struct Recrypt<'a> {
+    pub(crate) from: Option<&'a Cipher>,
+    pub(crate) to: Option<&'a Cipher>,
+}
+
+/// Where relocated pieces come from, an archive or a layout planned before, whose pieces are
+/// taken straight from the archives they were planned from.
+pub(crate) enum Pieces<'p, 'a> {
+    Archive(&'a CatReader, Recrypt<'a>),
+    Layout(&'p Layout<'a>, Option<&'a Cipher>),
+}
+
+/// A piece of an archive and where it goes in a [Layout].
+#[derive(Clone)]
+struct Transfer<'a> {
+    reader: &'a CatReader,
+    piece: Piece,
+    offset: u32,
+    size: u32,
+    recrypt: Recrypt<'a>,
+    relocated: u32,
+    context: EvalContext,
+}
+
+impl Transfer<'_> {
+    /// The size of the piece once it is moved to the other encryption.
+    fn relocated_size(&self) -> Option<u32> {
+        let overhead = |cipher: Option<&Cipher>| cipher.map_or(0, |_| Cipher::OVERHEAD as u32);
+        self.size
+            .checked_sub(overhead(self.recrypt.from))?
+            .checked_add(overhead(self.recrypt.to))
+    }
+}
+
+/// Where the pieces of one source archive ended up in a [Layout].
+struct Relocation<'s, 'p, 'a> {
+    source: &'s Header,
+    pieces: Pieces<'p, 'a>,
+    ranges: HashMap<(u32, u32), (u32, u32)>,
+    dictionaries: HashMap<u16, u16>,
+    blocks: HashMap<u32, u32>,
+    chunks: HashMap<u32, u32>,
+}
+
+impl<'a> Relocation<'_, '_, 'a> {
+    fn entries(
+        &mut self,
+        layout: &mut Layout<'a>,
+        entries: &mut [Entry],
+        context: &EvalContext,
+    ) -> meta::error::Result<()> {
+        for entry in entries {
+            match entry {
+                Entry::Directory { name, entries, .. } => {
+                    self.entries(layout, entries, &context.push(name.clone()))?
+                }
+                Entry::File {
+                    name,
+                    offset,
+                    size,
+                    attributes,
+                    ..
+                } => {
+                    let context = context.push(name.clone());
+                    if let Some(dictionary) = attributes.dictionary {
+                        attributes.dictionary = Some(self.dictionary(layout, dictionary, &context)?);
+                    }
+                    if let Some(chunks) = &mut attributes.chunks {
+                        for chunk in chunks {
+                            *chunk = self.chunk(layout, *chunk, &context)?;
+                        }
+                    } else if let Some(block) = attributes.block {
+                        attributes.block = Some(self.block(layout, block, &context)?);
+                    } else {
+                        (*offset, *size) = match self.ranges.get(&(*offset, *size)) {
+                            Some(relocated) => *relocated,
+                            None => {
+                                let relocated = layout.copy(&self.pieces, Piece::File, *offset, *size, &context)?;
+                                self.ranges.insert((*offset, *size), relocated);
+                                relocated
+                            }
+                        };
+                    }
+                }
+                Entry::Symlink { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn dictionary(&mut self, layout: &mut Layout<'a>, index: u16, context: &EvalContext) -> meta::error::Result<u16> {
+        if let Some(relocated) = self.dictionaries.get(&index) {
+            return Ok(*relocated);
+        }
+        let Some(dictionary) = self.source.dictionaries.get(index as usize) else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+        let relocated = u16::try_from(layout.dictionaries.len()).expect("Failed to convert usize to u16");
+        let (offset, size) = layout.copy(&self.pieces, Piece::Dictionary, dictionary.offset, dictionary.size, context)?;
+        layout.dictionaries.push(Dictionary {
+            offset,
+            size,
+        });
+        self.dictionaries.insert(index, relocated);
+        Ok(relocated)
+    }
+
+    fn block(&mut self, layout: &mut Layout<'a>, index: u32, context: &EvalContext) -> meta::error::Result<u32> {
+        if let Some(relocated) = self.blocks.get(&index) {
+            return Ok(*relocated);
+        }
+        let Some(block) = self.source.blocks.get(index as usize) else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+        let relocated = u32::try_from(layout.blocks.len()).expect("Failed to convert usize to u32");
+        let (offset, size) = layout.copy(&self.pieces, Piece::Block, block.offset, block.size, context)?;
+        layout.blocks.push(Block {
+            offset,
+            size,
+            compression: block.compression.clone(),
+        });
+        self.blocks.insert(index, relocated);
+        Ok(relocated)
+    }
+
+    fn chunk(&mut self, layout: &mut Layout<'a>, index: u32, context: &EvalContext) -> meta::error::Result<u32> {
+        if let Some(relocated) = self.chunks.get(&index) {
+            return Ok(*relocated);
+        }
+        let Some(chunk) = self.source.chunks.get(index as usize) else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+        let relocated = u32::try_from(layout.chunks.len()).expect("Failed to convert usize to u32");
+        let (offset, size) = layout.copy(&self.pieces, Piece::Chunk, chunk.offset, chunk.size, context)?;
+        layout.chunks.push(Chunk {
+            offset,
+            size,
+            compression: chunk.compression.clone(),
+        });
+        self.chunks.insert(index, relocated);
+        Ok(relocated)
+    }
+}
+
+/// Opens an archive to be rewritten in place and returns how much of its data section is kept,
+/// everything up to the end of the last piece its header refers to. A signature can't survive the
+/// rewrite, so it is dropped along with anything else after that, the archive has to be signed
+/// again afterwards. Only checking the signature reads the whole archive.
+pub(crate) fn open(archive: &Path, context: &Context) -> meta::error::Result<(CatReader, u64)> {
+    let reader = CatReader::open_with(archive, context)?;
+    let size = reader.header().data_end();
+    if size > reader.data_size() {
+        return CatError::InvalidEntryData(EvalContext::new("data".to_string())).into();
+    }
+
+    let trailer = u32::try_from(size)
+        .ok()
+        .filter(|_| reader.data_size() - size == SIGNATURE_LENGTH as u64)
+        .map(|offset| reader.read_raw(offset, SIGNATURE_LENGTH as u32, &EvalContext::new("signature".to_string())))
+        .transpose()?;
+    let signed = context.verifying_key.is_some() || trailer.is_some_and(|trailer| trailer.ends_with(&SIGNATURE_MAGIC));
+    if signed {
+        eprintln!("Removing the signature of {}, it has to be signed again", archive.display());
+    }
+    Ok((reader, size))
+}
+
+fn collect_blobs<'a>(
+    entries: &'a [Entry],
+    seen: &mut HashSet<Location>,
+    files: &mut Vec<(&'a Entry, EntryData)>,
+) {
+    for entry in entries {
+        match entry {
+            Entry::Directory { entries, .. } => collect_blobs(entries, seen, files),
+            Entry::File {
+                offset,
+                size,
+                compression,
+                attributes,
+                ..
+            } => {
+                let attributes = Attributes {
+                    mode: None,
+                    modified: None,
+                    ..attributes.clone()
+                };
+                if seen.insert((*offset, *size, compression.clone(), attributes.clone())) {
+                    files.push((
+                        entry,
+                        EntryData {
+                            compression: compression.clone(),
+                            attributes,
+                            size: *size,
+                            offset: *offset,
+                        },
+                    ));
+                }
+            }
+            Entry::Symlink { .. } => {}
+        }
+    }
+}
+
+/// Merges entries into a directory listing. Directories present in both are merged recursively,
+/// any other entry of the same name is resolved as the conflict policy says.
+fn merge_entries(
+    entries: &mut Vec<Entry>,
+    new: Vec<Entry>,
+    conflict: Conflict,
+    context: &Context,
+    parent: &str,
+) -> meta::error::Result<()> {
+    for entry in new {
+        let path = match parent {
+            "" => entry.name().to_string(),
+            parent => format!("{parent}/{}", entry.name()),
+        };
+        match entries.iter_mut().find(|existing| existing.name() == entry.name()) {
+            Some(Entry::Directory { entries, .. }) if matches!(entry, Entry::Directory { .. }) => {
+                if let Entry::Directory { entries: new, .. } = entry {
+                    merge_entries(entries, new, conflict, context, &path)?;
+                }
+            }
+            Some(existing) => match conflict {
+                Conflict::First => {
+                    if context.verbose {
+                        println!("Keeping the first {path}");
+                    }
+                }
+                Conflict::Last => {
+                    if context.verbose {
+                        println!("Replacing {path}");
+                    }
+                    *existing = entry;
+                }
+                Conflict::Error => return CatError::EntryExists(path).into(),
+            },
+            None => entries.push(entry),
+        }
+    }
+    entries.sort_by(|a, b| a.name().cmp(b.name()));
+    Ok(())
+}
@@ -1,2 +1,11 @@
 pub mod packing;
+pub mod manifest;
+pub mod editing;
+pub mod signing;
+pub mod tarball;
+pub mod zipfile;
+#[cfg(feature = "serde")]
+pub mod dump;
+mod converting;
+mod dedup;
 mod serializing;
\ No newline at end of file
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use meta::error::CatError;
+
+/// Maps paths inside the archive to the files or directories on disk they are packed from, so the
+/// layout of the archive doesn't have to mirror a single directory. Directories are packed with
+/// everything below them, relative source paths are resolved against the working directory.
+pub struct Manifest {
+    pub entries: Vec<(String, PathBuf)>,
+}
+
+impl Manifest {
+    /// One entry per line, the `/` separated archive path followed by whitespace and the source
+    /// path. A line holding only a path uses it for both, empty lines and lines starting with `#`
+    /// are skipped.
+    pub fn from_text(text: &str) -> meta::error::Result<Manifest> {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.split_once(char::is_whitespace) {
+                Some((archive, source)) => (archive.to_string(), PathBuf::from(source.trim_start())),
+                None => (line.to_string(), PathBuf::from(line)),
+            })
+            .collect();
+
+        Ok(Manifest { entries })
+    }
+
+    /// A JSON object with the archive paths as keys and the source paths as values.
+    pub fn from_json(json: &str) -> meta::error::Result<Manifest> {
+        let entries = serde_json::from_str::<BTreeMap<String, PathBuf>>(json).map_err(|err| {
+            CatError::InvalidManifest {
+                entry: format!("line {}", err.line()),
+                error: err.to_string(),
+            }
+        })?;
+
+        Ok(Manifest {
+            entries: entries.into_iter().collect(),
+        })
+    }
+}
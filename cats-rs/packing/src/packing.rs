@@ -1,158 +1,1171 @@
 use meta::error::CatError;
-use meta::metadata::{Compression, Entry, Header, MAGIC_NUMBER};
+use meta::crypto::{Cipher, Piece};
+use meta::metadata::{Attributes, Block, Chunk, Compression, Dictionary, Encryption, Entry, Header, MAGIC_NUMBER};
+use crate::dedup::dedup_keys;
+use crate::manifest::Manifest;
 use crate::serializing::CatSerializable;
+use unpacking::reader::CatReader;
 use meta::utils::{validate_name, wrap_context, EvalContext};
-use meta::Context;
+use meta::{Context, Solid};
+use crc32c::crc32c;
+use fastcdc::v2020::FastCDC;
 use flate2::read::GzEncoder;
-use hex::ToHex;
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use xxhash_rust::xxh3::xxh3_64;
+use zstd::dict::EncoderDictionary;
+use ignore::gitignore::Gitignore;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::Match;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::fs::{DirEntry, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::fs::{DirEntry, File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
 pub fn pack(directory: &Path, target: &Path, context: &Context) -> meta::error::Result<()> {
-    let mut serialized = HashMap::<String, EntryData>::new();
+    let filter = Filter::new(directory, Some(target), context)?;
+    write_archive(target, context, || {
+        walk(
+            directory,
+            context,
+            &filter.enter(directory)?,
+            &EvalContext::new("Archiving".to_string()),
+        )
+    })
+}
+
+/// Packs the files and directories listed in the manifest at the paths it maps them to.
+pub fn pack_manifest(manifest: &Manifest, target: &Path, context: &Context) -> meta::error::Result<()> {
+    write_archive(target, context, || manifest_nodes(manifest, target, context))
+}
+
+/// Collects the nodes and writes the archive, both on a pool of `context.jobs` threads.
+pub(crate) fn write_archive(
+    target: &Path,
+    context: &Context,
+    nodes: impl FnOnce() -> meta::error::Result<Vec<Node>> + Send,
+) -> meta::error::Result<()> {
+    let pool = create_pool(context)?;
+    let (header, data, cipher) = pool.install(|| serialize_tree(nodes()?, Base::default(), context))?;
+    write(target, header, cipher.as_ref(), context.encrypt_header, |output| {
+        output.write_all(&data).map_err(write_error(target))
+    })
+}
+
+pub(crate) fn create_pool(context: &Context) -> meta::error::Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(context.jobs)
+        .build()
+        .map_err(|err| CatError::UnableToStartWorkers(err.to_string()))
+}
+
+/// Writes a file next to the target and renames it over the target once it is complete, so an
+/// existing target is never left half written. The target keeps its permissions.
+pub(crate) fn replace_file(
+    target: &Path,
+    write: impl FnOnce(&mut File) -> meta::error::Result<()>,
+) -> meta::error::Result<()> {
+    let error = write_error(target);
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = target.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temporary)
+            .map_err(&error)?;
+        write(&mut file)?;
+        file.sync_all().map_err(&error)?;
+        if let Ok(metadata) = fs::metadata(target) {
+            fs::set_permissions(&temporary, metadata.permissions()).map_err(&error)?;
+        }
+        fs::rename(&temporary, target).map_err(&error)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+pub(crate) fn write_error(target: &Path) -> impl Fn(std::io::Error) -> CatError + '_ {
+    move |err| CatError::ErrorWritingFile {
+        path: target.display().to_string(),
+        error: err.to_string(),
+    }
+}
+
+/// Writes the header to the target, followed by the data section `data` writes, replacing the
+/// target only once everything is written. The header of an encrypted archive is either sealed or
+/// authenticated with the cipher.
+pub(crate) fn write(
+    target: &Path,
+    mut header: Header,
+    cipher: Option<&Cipher>,
+    seal: bool,
+    data: impl FnOnce(&mut dyn Write) -> meta::error::Result<()>,
+) -> meta::error::Result<()> {
+    let context = EvalContext::new("pack".to_string());
+    header.authentication = None;
+    match (cipher, seal) {
+        (Some(cipher), true) => header = seal_header(header, cipher)?,
+        (Some(_), false) => {
+            // A checksum of the plaintext in the clear would tell whether a file holds a guessed content.
+            strip_checksums(&mut header.entries);
+            header.authentication = Some(vec![0; Cipher::OVERHEAD]);
+        }
+        (None, _) => {}
+    }
+    header.version = header.required_version();
+    let mut serialized = Vec::<u8>::new();
+    header.serialize(&mut serialized, header.version, context.clone())?;
+    if let (Some(cipher), Some(_)) = (cipher, &header.authentication) {
+        cipher.authenticate(&mut serialized, &context)?;
+    }
+
+    replace_file(target, |file| {
+        let mut output = BufWriter::new(file);
+        output.write_all(&MAGIC_NUMBER).map_err(write_error(target))?;
+        output.write_all(&serialized).map_err(write_error(target))?;
+        data(&mut output)?;
+        output.flush().map_err(write_error(target))
+    })
+}
+
+pub(crate) enum Node {
+    Directory {
+        name: String,
+        children: Vec<Node>,
+        attributes: Attributes,
+    },
+    File {
+        name: String,
+        path: PathBuf,
+        content: Content,
+        /// The content as a gzip member, stored as it is instead of compressing the content again
+        /// when gzip is wanted at the default level.
+        gzip: Option<Vec<u8>>,
+        attributes: Attributes,
+    },
+    Symlink {
+        name: String,
+        target: String,
+    },
+}
+
+/// Where the content of a file node is read from.
+#[derive(Clone)]
+pub(crate) enum Content {
+    /// The file at the path of the node.
+    Path,
+    Memory(Vec<u8>),
+    /// A file the content was spooled to while reading a stream, the path of the node is only
+    /// where it goes in the archive.
+    Spooled(PathBuf),
+}
+
+/// Ignore files with gitignore semantics, applying to the directory they are in and below.
+const IGNORE_FILE: &str = ".catsignore";
+
+/// The archive layout described by a manifest, before the sources are read.
+enum ManifestTree {
+    Directory(BTreeMap<String, ManifestTree>),
+    Source(PathBuf),
+}
+
+/// The archive new files are appended to, its data section ends at `size`. Files equal to one of
+/// the `existing` blobs point to it instead of being stored again, a blob is only read from the
+/// archive when a new file may be equal to it. Appending doesn't train dictionaries or split
+/// chunks, as their indices would have to start after the ones already in use.
+#[derive(Default)]
+pub(crate) struct Base<'a> {
+    pub(crate) size: usize,
+    pub(crate) dictionaries: Vec<Dictionary>,
+    pub(crate) blocks: Vec<Block>,
+    pub(crate) chunks: Vec<Chunk>,
+    pub(crate) encryption: Option<(Encryption, Cipher)>,
+    pub(crate) reader: Option<&'a CatReader>,
+    pub(crate) existing: Vec<(&'a Entry, EntryData)>,
+}
+
+/// Files at most this large are used to train the dictionary and are compressed with it.
+const DICTIONARY_SAMPLE_LIMIT: usize = 128 * 1024;
+const DICTIONARY_MIN_SAMPLES: usize = 8;
+const DICTIONARY_MIN_SIZE: usize = 256;
+const DICTIONARY_RATIO: usize = 16;
+/// At most this many times the dictionary size is read to train it.
+const DICTIONARY_SAMPLE_BUDGET: usize = 100;
+
+/// Where the content of a blob comes from. Files on disk are read again whenever their content is
+/// needed instead of being kept in memory.
+enum Source<'a> {
+    Memory(Vec<u8>),
+    Disk(Cow<'a, Path>),
+}
+
+impl Source<'_> {
+    fn load(&self) -> meta::error::Result<Cow<'_, [u8]>> {
+        match self {
+            Source::Memory(content) => Ok(Cow::Borrowed(content)),
+            Source::Disk(path) => fs::read(path).map(Cow::Owned).map_err(|err| CatError::ErrorReadingFile {
+                path: path.display().to_string(),
+                error: err.to_string(),
+            }),
+        }
+    }
+}
+
+/// What is known about the content of a file without keeping it around.
+struct Scan {
+    size: usize,
+    hash: u64,
+    checksum: Option<u32>,
+    /// The content defined chunks with their hashes, empty unless the file is split into several.
+    chunks: Vec<(Range<usize>, u64)>,
+}
+
+/// A distinct blob and the path it was first found at.
+struct Blob<'a> {
+    path: &'a Path,
+    source: Source<'a>,
+    gzip: Option<Vec<u8>>,
+    scan: Scan,
+}
+
+/// A blob compressed on its own, a solid block of blobs compressed together or the new chunks of
+/// a blob, compressed one by one. Blobs are referred to by their index.
+enum Unit<'a> {
+    Blob(usize, &'a Path),
+    Block(&'a Path, Vec<usize>),
+    Chunks(usize, &'a Path, Vec<Range<usize>>),
+}
+
+enum Compressed {
+    Blob(usize, Compression, Attributes, Vec<u8>),
+    Block(Compression, Vec<u8>, Vec<(usize, u32, u32)>),
+    Chunks(Vec<(Compression, Vec<u8>)>),
+}
+
+#[derive(Clone)]
+pub(crate) struct EntryData {
+    pub(crate) compression: Compression,
+    pub(crate) attributes: Attributes,
+    pub(crate) size: u32,
+    pub(crate) offset: u32,
+}
+
+/// Walks, hashes and compresses the tree on the current thread pool. Only the
+/// dedup and the data layout run sequentially, in walk order, so the output
+/// doesn't depend on how the work was scheduled. Returns the data to append
+/// to the data section of the base.
+pub(crate) fn serialize_tree(
+    nodes: Vec<Node>,
+    base: Base,
+    context: &Context,
+) -> meta::error::Result<(Header, Vec<u8>, Option<Cipher>)> {
+    let mut nodes = nodes;
+    let mut loaded = Vec::<Content>::new();
+    let mut gzips = Vec::<Option<Vec<u8>>>::new();
+    take_contents(&mut nodes, &mut loaded, &mut gzips);
+    let mut files = Vec::<&Path>::new();
+    collect_files(&nodes, &mut files);
+
+    let sources = files
+        .iter()
+        .zip(loaded)
+        .map(|(path, content)| match content {
+            Content::Path => Source::Disk(Cow::Borrowed(path)),
+            Content::Memory(content) => Source::Memory(content),
+            Content::Spooled(path) => Source::Disk(Cow::Owned(path)),
+        })
+        .collect::<Vec<Source>>();
+
+    // Only what dedup and chunking need is kept, the content is read again when it is compressed.
+    let scans = files
+        .par_iter()
+        .zip(&sources)
+        .map(|(path, source)| {
+            if context.verbose {
+                println!("Serializing file {}", path.display())
+            }
+            Ok(scan(&source.load()?, context))
+        })
+        .collect::<meta::error::Result<Vec<Scan>>>()?;
+
+    // Blobs are numbered after the existing ones, which come first so files dedup against them.
+    // Those are only decoded if their size is unknown or matches one of the new files.
+    let known = base.existing.len();
+    let sizes = scans.iter().map(|scan| scan.size as u64).collect::<HashSet<u64>>();
+    let existing = |index: usize| {
+        let (entry, _) = base.existing[index];
+        let reader = base.reader.expect("Expected a reader for the existing blobs");
+        reader.read_entry(entry, &EvalContext::new(entry.name().to_string()))
+    };
+    let keys = base
+        .existing
+        .par_iter()
+        .enumerate()
+        .map(|(index, (entry, _))| {
+            let reader = base.reader.expect("Expected a reader for the existing blobs");
+            match reader.content_size(entry, &EvalContext::new(entry.name().to_string()))? {
+                Some(size) if !sizes.contains(&size) => Ok((size as usize, None)),
+                _ => {
+                    let content = existing(index)?;
+                    let size = content.len();
+                    Ok((size, sizes.contains(&(size as u64)).then(|| xxh3_64(&content))))
+                }
+            }
+        })
+        .collect::<meta::error::Result<Vec<(usize, Option<u64>)>>>()?
+        .into_iter()
+        .chain(scans.iter().map(|scan| (scan.size, Some(scan.hash))))
+        .collect::<Vec<(usize, Option<u64>)>>();
+    let content = |index: usize| match index < known {
+        true => existing(index).map(Cow::Owned),
+        false => sources[index - known].load(),
+    };
+    let firsts = dedup_keys(&keys, |a, b| Ok::<bool, CatError>(content(a)? == content(b)?))?;
+
+    let mut blobs = Vec::<Blob>::new();
+    let mut blob_indices = (0..known).collect::<Vec<usize>>();
+    let mut file_blobs = Vec::<usize>::with_capacity(files.len());
+    for (index, (((path, source), scan), gzip)) in files.iter().zip(sources).zip(scans).zip(gzips).enumerate() {
+        let index = known + index;
+        if firsts[index] == index {
+            blob_indices.push(known + blobs.len());
+            blobs.push(Blob {
+                path,
+                source,
+                gzip,
+                scan,
+            });
+        } else {
+            blob_indices.push(blob_indices[firsts[index]]);
+        }
+        file_blobs.push(blob_indices[index]);
+    }
+    let blob_count = blobs.len();
+    let checksums = blobs.iter().map(|blob| blob.scan.checksum).collect::<Vec<Option<u32>>>();
+
+    let level = compression_level(context)?;
+    let encryption = match (base.encryption, &context.password) {
+        (Some(encryption), _) => Some(encryption),
+        (None, Some(password)) => {
+            let encryption = Encryption::generate();
+            let cipher = Cipher::new(password, &encryption, &EvalContext::new("encryption".to_string()))?;
+            Some((encryption, cipher))
+        }
+        (None, None) => None,
+    };
+    let cipher = encryption.as_ref().map(|(_, cipher)| cipher);
+    let (remaining, chunk_units, chunked) = split_chunks(&blobs, context)?;
+    let mut units = group_blocks(remaining, &blobs, context);
+    let dictionary = train_dictionary(&units, &blobs, context)?;
+    units.extend(chunk_units);
+    let encoder_dictionary = dictionary
+        .as_ref()
+        .map(|dictionary| EncoderDictionary::copy(dictionary, level as i32));
+
+    let compressed = units
+        .into_par_iter()
+        .map(|unit| match unit {
+            Unit::Blob(index, path) => {
+                let eval_context = EvalContext::new(path.display().to_string());
+                if let Some(gzip) = &blobs[index].gzip
+                    && context.compression == Compression::Gzip
+                    && context.level.is_none()
+                {
+                    return Ok(Compressed::Blob(index, Compression::Gzip, Attributes::default(), gzip.clone()));
+                }
+                let content = blobs[index].source.load()?;
+                let dictionary = encoder_dictionary
+                    .as_ref()
+                    .filter(|_| content.len() <= DICTIONARY_SAMPLE_LIMIT);
+                let (compression, attributes, content) = compress(&content, context, level, dictionary, &eval_context)?;
+                Ok(Compressed::Blob(index, compression, attributes, content))
+            }
+            Unit::Block(path, members) => {
+                let mut block = Vec::<u8>::new();
+                let mut files = Vec::<(usize, u32, u32)>::with_capacity(members.len());
+                for index in members {
+                    let content = blobs[index].source.load()?;
+                    let offset = u32::try_from(block.len()).expect("Failed to convert usize to u32");
+                    let size = u32::try_from(content.len()).expect("Failed to convert usize to u32");
+                    block.extend_from_slice(&content);
+                    files.push((index, offset, size));
+                }
+                let (compression, _, content) = compress(
+                    &block,
+                    context,
+                    level,
+                    None,
+                    &EvalContext::new(path.display().to_string()),
+                )?;
+                Ok(Compressed::Block(compression, content, files))
+            }
+            Unit::Chunks(index, path, ranges) => {
+                let content = blobs[index].source.load()?;
+                let eval_context = EvalContext::new(path.display().to_string());
+                let chunks = ranges
+                    .into_iter()
+                    .map(|range| {
+                        let (compression, _, content) =
+                            compress(&content[range], context, level, None, &eval_context)?;
+                        Ok((compression, content))
+                    })
+                    .collect::<meta::error::Result<Vec<(Compression, Vec<u8>)>>>()?;
+                Ok(Compressed::Chunks(chunks))
+            }
+        })
+        .collect::<meta::error::Result<Vec<Compressed>>>()?;
+
+    // Offsets are handed out before sealing, every piece grows by the same amount when it is sealed.
+    // Each piece is bound to its offset, so it can only be sealed once that is known.
     let mut data = Vec::<u8>::new();
-    let entry = create_entry(
-        directory,
-        &mut serialized,
-        &mut data,
-        context,
-        &EvalContext::new("Archiving".to_string()),
-    )?;
-
-    let header = match entry {
-        Entry::File { .. } => panic!("Root is file and not directory!"),
-        Entry::Directory { entries, .. } => Header {
-            version: 1,
-            entries,
-        },
+    let mut pieces = Vec::<(Piece, u32, Vec<u8>)>::new();
+    let mut end = base.size;
+    let mut place = |piece: Piece, content: Vec<u8>| {
+        let offset = u32::try_from(end).expect("Failed to convert usize to u32");
+        let size = content.len() + cipher.map_or(0, |_| Cipher::OVERHEAD);
+        let size = u32::try_from(size).expect("Failed to convert usize to u32");
+        end += size as usize;
+        pieces.push((piece, offset, content));
+        (offset, size)
     };
 
-    let mut file = match OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(target)
-    {
-        Ok(file) => file,
-        Err(err) => panic!("Failed to create file {} ({})", target.display(), err),
+    let mut dictionaries = base.dictionaries;
+    if let Some(dictionary) = dictionary {
+        let (offset, size) = place(Piece::Dictionary, dictionary);
+        dictionaries.push(Dictionary {
+            offset,
+            size,
+        });
+    }
+    let mut blocks = base.blocks;
+    let mut chunks = base.chunks;
+    let mut blob_data = vec![None; blob_count];
+    for (index, data) in chunked {
+        blob_data[index] = Some(data);
+    }
+    for compressed in compressed {
+        match compressed {
+            Compressed::Blob(index, compression, attributes, content) => {
+                let (offset, size) = place(Piece::File, content);
+                blob_data[index] = Some(EntryData {
+                    compression,
+                    attributes,
+                    size,
+                    offset,
+                });
+            }
+            Compressed::Block(compression, content, files) => {
+                let block = u32::try_from(blocks.len()).expect("Failed to convert usize to u32");
+                let (offset, size) = place(Piece::Block, content);
+                blocks.push(Block {
+                    offset,
+                    size,
+                    compression,
+                });
+                for (index, offset, size) in files {
+                    blob_data[index] = Some(EntryData {
+                        compression: Compression::None,
+                        attributes: Attributes {
+                            block: Some(block),
+                            ..Default::default()
+                        },
+                        size,
+                        offset,
+                    });
+                }
+            }
+            Compressed::Chunks(list) => {
+                for (compression, content) in list {
+                    let (offset, size) = place(Piece::Chunk, content);
+                    chunks.push(Chunk {
+                        offset,
+                        size,
+                        compression,
+                    });
+                }
+            }
+        }
+    }
+    let pieces = match cipher {
+        Some(cipher) => pieces
+            .into_par_iter()
+            .map(|(piece, offset, content)| {
+                cipher.seal(&content, &piece.at(offset), &EvalContext::new(format!("data at {offset}")))
+            })
+            .collect::<meta::error::Result<Vec<Vec<u8>>>>()?,
+        None => pieces.into_iter().map(|(_, _, content)| content).collect(),
     };
-    file.write_all(&MAGIC_NUMBER).map_err(|x| {
-        CatError::ErrorWritingFile {
-            path: target.display().to_string(),
-            error: x.to_string(),
+    for piece in pieces {
+        data.extend(piece);
+    }
+    let blob_data = base
+        .existing
+        .into_iter()
+        .map(|(_, data)| data)
+        .chain(blob_data.into_iter().zip(checksums).map(|(data, checksum)| {
+            let mut data = data.expect("Expected every blob to be laid out");
+            data.attributes.checksum = checksum;
+            data
+        }))
+        .collect::<Vec<EntryData>>();
+
+    let mut files = file_blobs.into_iter().map(|index| &blob_data[index]);
+    let entries = nodes
+        .into_iter()
+        .map(|node| create_entry(node, &mut files))
+        .collect();
+
+    let (encryption, cipher) = encryption.unzip();
+    let header = Header {
+        version: 1,
+        dictionaries,
+        blocks,
+        chunks,
+        encryption,
+        sealed: None,
+        authentication: None,
+        entries,
+    };
+
+    Ok((header, data, cipher))
+}
+
+fn strip_checksums(entries: &mut [Entry]) {
+    for entry in entries {
+        match entry {
+            Entry::Directory { entries, .. } => strip_checksums(entries),
+            Entry::File { attributes, .. } => attributes.checksum = None,
+            Entry::Symlink { .. } => {}
         }
-    })?;
-    header.serialize(&mut file, EvalContext::new("pack".to_string()))?;
-    file.write_all(data.as_slice()).map_err(|x| {
-        CatError::ErrorWritingFile {
-            path: target.display().to_string(),
-            error: x.to_string(),
+    }
+}
+
+/// Moves everything but the encryption parameters into an encrypted complete header of its own.
+fn seal_header(mut header: Header, cipher: &Cipher) -> meta::error::Result<Header> {
+    let context = EvalContext::new("header".to_string());
+    let encryption = header.encryption.take();
+    header.version = header.required_version();
+    let mut inner = Vec::<u8>::new();
+    header.serialize(&mut inner, header.version, context.clone())?;
+
+    Ok(Header {
+        version: 2,
+        dictionaries: Vec::new(),
+        blocks: Vec::new(),
+        chunks: Vec::new(),
+        encryption,
+        sealed: Some(cipher.seal(&inner, &Piece::SealedHeader.at(0), &context)?),
+        authentication: None,
+        entries: Vec::new(),
+    })
+}
+
+/// Decides which paths below the input directory get packed, from the CLI globs and the
+/// `.catsignore` files of the directories walked so far.
+#[derive(Clone)]
+struct Filter {
+    overrides: Override,
+    ignores: Vec<Arc<Gitignore>>,
+    output: Option<PathBuf>,
+}
+
+impl Filter {
+    fn new(directory: &Path, target: Option<&Path>, context: &Context) -> meta::error::Result<Filter> {
+        let mut builder = OverrideBuilder::new(directory);
+        // Overrides include by default and exclude when negated, the later globs win.
+        let globs = context
+            .include
+            .iter()
+            .map(|glob| (glob, glob.clone()))
+            .chain(context.exclude.iter().map(|glob| (glob, format!("!{glob}"))));
+        for (glob, pattern) in globs {
+            builder.add(&pattern).map_err(|err| CatError::InvalidPattern {
+                pattern: glob.clone(),
+                error: err.to_string(),
+            })?;
         }
-    })?;
-    file.flush().map_err(|x| {
-        CatError::ErrorWritingFile {
-            path: target.display().to_string(),
-            error: x.to_string(),
+        let overrides = builder.build().map_err(|err| CatError::InvalidPattern {
+            pattern: directory.display().to_string(),
+            error: err.to_string(),
+        })?;
+
+        // The archive may not exist yet, so only its directory can be resolved.
+        let output = target.and_then(|target| {
+            let parent = target.parent().filter(|parent| !parent.as_os_str().is_empty());
+            fs::canonicalize(parent.unwrap_or(Path::new(".")))
+                .ok()
+                .zip(target.file_name())
+                .map(|(parent, name)| parent.join(name))
+        });
+
+        Ok(Filter {
+            overrides,
+            ignores: Vec::new(),
+            output,
+        })
+    }
+
+    /// Picks up the `.catsignore` of a directory before walking it.
+    fn enter(&self, directory: &Path) -> meta::error::Result<Filter> {
+        let path = directory.join(IGNORE_FILE);
+        if !path.is_file() {
+            return Ok(self.clone());
         }
-    })?;
 
-    Ok(())
-}
+        let (ignore, error) = Gitignore::new(&path);
+        if let Some(error) = error {
+            return Err(CatError::InvalidPattern {
+                pattern: path.display().to_string(),
+                error: error.to_string(),
+            });
+        }
+        let mut filter = self.clone();
+        filter.ignores.push(Arc::new(ignore));
+        Ok(filter)
+    }
 
-struct EntryData {
-    compression: Compression,
-    size: u32,
-    offset: u32,
+    /// Explicit globs win over ignore files, deeper ignore files win over the ones above them.
+    fn excludes(&self, path: &Path, is_dir: bool) -> bool {
+        if let Some(output) = &self.output
+            && path.file_name() == output.file_name()
+            && fs::canonicalize(path).is_ok_and(|path| path == *output)
+        {
+            return true;
+        }
+
+        match self.overrides.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+        for ignore in self.ignores.iter().rev() {
+            match ignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
 }
 
-fn create_entry(
+fn walk(
     path: &Path,
-    serialized: &mut HashMap<String, EntryData>,
-    vec: &mut Vec<u8>,
+    context: &Context,
+    filter: &Filter,
+    eval_context: &EvalContext,
+) -> meta::error::Result<Vec<Node>> {
+    if context.verbose {
+        println!("Serializing directory {}", path.display())
+    }
+    let mut children = fs::read_dir(path)
+        .map_err(|err| CatError::ErrorReadingFile {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        })?
+        .filter_map(std::result::Result::ok)
+        .collect::<Vec<DirEntry>>();
+    // The order read_dir returns depends on the file system, sorting keeps the output reproducible.
+    children.sort_by_key(DirEntry::file_name);
+
+    let nodes = children
+        .par_iter()
+        .map(|x| {
+            let path = x.path();
+            let is_link = x.file_type().is_ok_and(|file_type| file_type.is_symlink());
+            let keep_link = is_link && context.symlinks;
+            let is_dir = !keep_link && path.is_dir();
+            if filter.excludes(&path, is_dir) {
+                return Ok(None);
+            }
+
+            let eval_context = eval_context.push(path.display().to_string());
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let name = validate_name(name, &eval_context)?;
+
+            create_node(path, name, is_link, context, filter, &eval_context)
+        })
+        .collect::<meta::error::Result<Vec<Option<Node>>>>()?;
+
+    Ok(nodes.into_iter().flatten().collect())
+}
+
+/// Builds the tree described by the manifest, creating the directories it implies.
+pub(crate) fn manifest_nodes(manifest: &Manifest, target: &Path, context: &Context) -> meta::error::Result<Vec<Node>> {
+    let conflict = |entry: &String| CatError::InvalidManifest {
+        entry: entry.clone(),
+        error: "conflicts with another entry".to_string(),
+    };
+    let mut root = BTreeMap::<String, ManifestTree>::new();
+    for (entry, source) in &manifest.entries {
+        let eval_context = EvalContext::new(entry.clone());
+        let names = entry
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .map(|name| validate_name(name.to_string(), &eval_context))
+            .collect::<meta::error::Result<Vec<String>>>()?;
+        let Some((name, parents)) = names.split_last() else {
+            return CatError::InvalidEntryName(eval_context).into();
+        };
+
+        let mut directory = &mut root;
+        for parent in parents {
+            directory = match directory
+                .entry(parent.clone())
+                .or_insert_with(|| ManifestTree::Directory(BTreeMap::new()))
+            {
+                ManifestTree::Directory(children) => children,
+                ManifestTree::Source(_) => return Err(conflict(entry)),
+            };
+        }
+        if directory.insert(name.clone(), ManifestTree::Source(source.clone())).is_some() {
+            return Err(conflict(entry));
+        }
+    }
+
+    create_manifest_nodes(root, target, context, &EvalContext::new("manifest".to_string()))
+}
+
+fn create_manifest_nodes(
+    tree: BTreeMap<String, ManifestTree>,
+    target: &Path,
     context: &Context,
     eval_context: &EvalContext,
-) -> meta::error::Result<Entry> {
+) -> meta::error::Result<Vec<Node>> {
+    let mut nodes = Vec::<Node>::new();
+    for (name, tree) in tree {
+        let eval_context = eval_context.push(name.clone());
+        let node = match tree {
+            ManifestTree::Directory(children) => Some(Node::Directory {
+                children: create_manifest_nodes(children, target, context, &eval_context)?,
+                name,
+                attributes: Attributes::default(),
+            }),
+            ManifestTree::Source(path) => {
+                let is_link = fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_symlink());
+                let filter = Filter::new(&path, Some(target), context)?;
+                create_node(path, name, is_link, context, &filter, &eval_context)?
+            }
+        };
+        nodes.extend(node);
+    }
+    Ok(nodes)
+}
+
+/// Turns a path into a node, walking it if it is a directory.
+fn create_node(
+    path: PathBuf,
+    name: String,
+    is_link: bool,
+    context: &Context,
+    filter: &Filter,
+    eval_context: &EvalContext,
+) -> meta::error::Result<Option<Node>> {
+    if is_link && context.symlinks {
+        let target = fs::read_link(&path).map_err(|err| CatError::ErrorReadingFile {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        })?;
+        let target = match target.to_str() {
+            Some(target) => target.to_string(),
+            None => return CatError::InvalidEntryName(eval_context.clone()).into(),
+        };
+        return Ok(Some(Node::Symlink { name, target }));
+    }
     if path.is_dir() {
-        if context.verbose {
-            println!("Serializing directory {}", path.display())
-        }
-        let mut entries = Vec::<Entry>::new();
-        for x in fs::read_dir(path)
-            .unwrap()
-            .into_iter()
-            .filter_map(std::result::Result::ok)
-            .collect::<Vec<DirEntry>>()
-        {
-            entries.push(create_entry(
-                x.path().as_path(),
-                serialized,
-                vec,
-                context,
-                &eval_context.push(x.path().as_path().display().to_string()),
-            )?)
-        }
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-
-        return Ok(Entry::Directory {
-            name: validate_name(name.clone(), eval_context)?,
-            entries,
-        });
+        let attributes = capture_attributes(&path, context)?;
+        let children = walk(path.as_path(), context, &filter.enter(&path)?, eval_context)?;
+        // Directories only kept for files that weren't included are left out.
+        if children.is_empty() && !context.include.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(Node::Directory {
+            name,
+            children,
+            attributes,
+        }));
     }
     if path.is_file() {
-        if context.verbose {
-            println!("Serializing file {}", path.display())
+        let attributes = capture_attributes(&path, context)?;
+        return Ok(Some(Node::File {
+            name,
+            path,
+            content: Content::Path,
+            gzip: None,
+            attributes,
+        }));
+    }
+
+    Err(CatError::ErrorReadingFile {
+        path: path.display().to_string(),
+        error: match is_link {
+            true => "dangling symbolic link".to_string(),
+            false => "neither a file nor a directory".to_string(),
+        },
+    })
+}
+
+/// Reads the permissions and modification time of a file or directory, as far as they are wanted.
+fn capture_attributes(path: &Path, context: &Context) -> meta::error::Result<Attributes> {
+    if !context.permissions && !context.times {
+        return Ok(Attributes::default());
+    }
+
+    let metadata = fs::metadata(path).map_err(|err| CatError::ErrorReadingFile {
+        path: path.display().to_string(),
+        error: err.to_string(),
+    })?;
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some((metadata.permissions().mode() & 0o777) as u16)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs() as i64);
+
+    Ok(stored_attributes(mode, modified, context))
+}
+
+/// Keeps the permissions and modification time as far as they are wanted, clamping the time to
+/// the source date epoch.
+pub(crate) fn stored_attributes(mode: Option<u16>, modified: Option<i64>, context: &Context) -> Attributes {
+    Attributes {
+        mode: mode.map(|mode| mode & 0o777).filter(|_| context.permissions),
+        modified: modified
+            .map(|modified| match context.source_date_epoch {
+                Some(epoch) => modified.min(epoch),
+                None => modified,
+            })
+            .filter(|_| context.times),
+        ..Default::default()
+    }
+}
+
+/// Moves the contents not read from the path of their node out of the nodes, in the order
+/// [collect_files] visits them.
+fn take_contents(nodes: &mut [Node], contents: &mut Vec<Content>, gzips: &mut Vec<Option<Vec<u8>>>) {
+    for node in nodes {
+        match node {
+            Node::Directory { children, .. } => take_contents(children, contents, gzips),
+            Node::File { content, gzip, .. } => {
+                contents.push(std::mem::replace(content, Content::Path));
+                gzips.push(gzip.take());
+            }
+            Node::Symlink { .. } => {}
         }
-        let content = fs::read(path).unwrap();
-        let hash = Sha256::digest(&content).encode_hex::<String>();
+    }
+}
 
-        let data = if serialized.contains_key(&hash) {
-            serialized.get(&hash).unwrap()
-        } else {
-            let offset = u32::try_from(vec.len()).expect("Failed to convert usize to u32");
-            let mut buff = Vec::<u8>::new();
-            let content = if context.gzip {
-                let mut meow = GzEncoder::new(&content[..], flate2::Compression::best());
-                wrap_context(
-                    meow.read_to_end(&mut buff),
-                    eval_context.push("gzip".to_string()),
-                    CatError::FailedToCompressData,
-                )?;
-                buff
-            } else {
-                content
-            };
-            let size = u32::try_from(content.len()).expect("Failed to convert usize to u32");
+fn collect_files<'a>(nodes: &'a [Node], files: &mut Vec<&'a Path>) {
+    for node in nodes {
+        match node {
+            Node::Directory { children, .. } => collect_files(children, files),
+            Node::File { path, .. } => files.push(path.as_path()),
+            Node::Symlink { .. } => {}
+        }
+    }
+}
 
-            let compression = if context.gzip {
-                Compression::Gzip
+fn compression_level(context: &Context) -> meta::error::Result<u32> {
+    let (default, range) = match context.compression {
+        Compression::Zstd => (19, 1..=22),
+        Compression::Gzip => (9, 1..=9),
+        Compression::None => (0, 0..=u32::MAX),
+    };
+    match context.level {
+        Some(level) if !range.contains(&level) => CatError::InvalidCompressionLevel(level).into(),
+        Some(level) => Ok(level),
+        None => Ok(default),
+    }
+}
+
+/// Reads the size, hash and checksum of a file and where it is cut into chunks.
+fn scan(content: &[u8], context: &Context) -> Scan {
+    let chunks = match context.chunk_size {
+        0 => Vec::new(),
+        average => {
+            let average = u32::try_from(average).expect("Failed to convert usize to u32");
+            let cuts = FastCDC::new(content, average / 4, average, average * 4)
+                .map(|chunk| chunk.offset..chunk.offset + chunk.length)
+                .collect::<Vec<Range<usize>>>();
+            match cuts.len() {
+                0 | 1 => Vec::new(),
+                _ => cuts
+                    .into_iter()
+                    .map(|cut| {
+                        let hash = xxh3_64(&content[cut.clone()]);
+                        (cut, hash)
+                    })
+                    .collect(),
+            }
+        }
+    };
+
+    Scan {
+        size: content.len(),
+        hash: xxh3_64(content),
+        checksum: context.checksums.then(|| crc32c(content)),
+        chunks,
+    }
+}
+
+/// The blobs stored as a whole, the units holding new chunks and the entry data of split blobs.
+type Split<'a> = (Vec<usize>, Vec<Unit<'a>>, Vec<(usize, EntryData)>);
+
+/// Dedups the content defined chunks of the blobs that were split. Returns the blobs that are
+/// stored as a whole, the units holding the new chunks and the entry data of the split blobs.
+fn split_chunks<'a>(
+    blobs: &[Blob<'a>],
+    context: &Context,
+) -> meta::error::Result<Split<'a>> {
+    if context.chunk_size == 0 {
+        return Ok(((0..blobs.len()).collect(), Vec::new(), Vec::new()));
+    }
+
+    let slices = blobs
+        .iter()
+        .enumerate()
+        .flat_map(|(index, blob)| blob.scan.chunks.iter().map(move |(cut, hash)| (index, cut, *hash)))
+        .collect::<Vec<(usize, &Range<usize>, u64)>>();
+    let keys = slices
+        .iter()
+        .map(|(_, cut, hash)| (cut.len(), Some(*hash)))
+        .collect::<Vec<(usize, Option<u64>)>>();
+    let firsts = dedup_keys(&keys, |a, b| {
+        let (a, a_cut, _) = slices[a];
+        let (b, b_cut, _) = slices[b];
+        Ok::<bool, CatError>(blobs[a].source.load()?[a_cut.clone()] == blobs[b].source.load()?[b_cut.clone()])
+    })?;
+
+    let mut units = Vec::<Unit>::new();
+    let mut chunk_count = 0u32;
+    let mut chunk_indices = Vec::<u32>::with_capacity(slices.len());
+    let mut slices = slices.into_iter().enumerate();
+    let mut remaining = Vec::<usize>::new();
+    let mut chunked = Vec::<(usize, EntryData)>::new();
+    for (index, blob) in blobs.iter().enumerate() {
+        if blob.scan.chunks.is_empty() {
+            remaining.push(index);
+            continue;
+        }
+
+        let mut chunks = Vec::<u32>::with_capacity(blob.scan.chunks.len());
+        let mut ranges = Vec::<Range<usize>>::new();
+        for (slice, (_, cut, _)) in slices.by_ref().take(blob.scan.chunks.len()) {
+            if firsts[slice] == slice {
+                chunk_indices.push(chunk_count);
+                chunk_count += 1;
+                ranges.push(cut.clone());
             } else {
-                Compression::None
-            };
-            serialized.insert(
-                hash,
-                EntryData {
-                    compression: compression.clone(),
-                    size,
-                    offset,
+                chunk_indices.push(chunk_indices[firsts[slice]]);
+            }
+            chunks.push(chunk_indices[slice]);
+        }
+        if !ranges.is_empty() {
+            units.push(Unit::Chunks(index, blob.path, ranges));
+        }
+        chunked.push((
+            index,
+            EntryData {
+                compression: Compression::None,
+                attributes: Attributes {
+                    chunks: Some(chunks),
+                    ..Default::default()
                 },
-            );
-            vec.extend(content);
+                size: u32::try_from(blob.scan.size).expect("Failed to convert usize to u32"),
+                offset: 0,
+            },
+        ));
+    }
 
-            &EntryData { size, offset, compression }
+    Ok((remaining, units, chunked))
+}
+
+/// Groups the blobs into solid blocks, in order of appearance. Blobs that don't fit into a block
+/// on their own are kept apart.
+fn group_blocks<'a>(indices: Vec<usize>, blobs: &[Blob<'a>], context: &Context) -> Vec<Unit<'a>> {
+    let mut units = Vec::<Unit>::new();
+    let mut open = HashMap::<String, (usize, usize)>::new();
+    for index in indices {
+        let (path, size) = (blobs[index].path, blobs[index].scan.size);
+        let key = match context.solid {
+            _ if size >= context.block_size => None,
+            Solid::None => None,
+            Solid::Directory => Some(path.parent().unwrap_or(path).display().to_string()),
+            Solid::Extension => Some(
+                path.extension()
+                    .map(|extension| extension.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            ),
+        };
+        let Some(key) = key else {
+            units.push(Unit::Blob(index, path));
+            continue;
         };
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
 
-        return Ok(Entry::File {
-            name: validate_name(name.clone(), eval_context)?,
-            offset: data.offset,
-            size: data.size,
-            compression: data.compression.clone(),
-        });
+        match open.get_mut(&key) {
+            Some((unit, total)) if *total + size <= context.block_size => {
+                *total += size;
+                if let Unit::Block(_, members) = &mut units[*unit] {
+                    members.push(index);
+                }
+            }
+            _ => {
+                open.insert(key, (units.len(), size));
+                units.push(Unit::Block(path, vec![index]));
+            }
+        }
+    }
+    units
+}
+
+/// Trains a zstd dictionary on the small blobs, the ones that suffer the most from starting with
+/// an empty window. Returns nothing if there is too little data to train on. Only as many samples
+/// are read as the dictionary size calls for.
+fn train_dictionary(units: &[Unit], blobs: &[Blob], context: &Context) -> meta::error::Result<Option<Vec<u8>>> {
+    if context.compression != Compression::Zstd || context.dictionary_size == 0 {
+        return Ok(None);
+    }
+
+    let budget = context.dictionary_size.saturating_mul(DICTIONARY_SAMPLE_BUDGET);
+    let mut total = 0usize;
+    let picked = units
+        .iter()
+        .filter_map(|unit| match unit {
+            Unit::Blob(index, _) => Some(*index),
+            Unit::Block(..) | Unit::Chunks(..) => None,
+        })
+        .filter(|index| blobs[*index].scan.size <= DICTIONARY_SAMPLE_LIMIT)
+        .take_while(|index| {
+            let within = total < budget;
+            total += blobs[*index].scan.size;
+            within
+        })
+        .collect::<Vec<usize>>();
+    let samples = picked
+        .par_iter()
+        .map(|index| blobs[*index].source.load().map(Cow::into_owned))
+        .collect::<meta::error::Result<Vec<Vec<u8>>>>()?;
+    // A dictionary much larger than what it is trained on costs more space than it saves.
+    let total = samples.iter().map(|content| content.len()).sum::<usize>();
+    let size = context.dictionary_size.min(total / DICTIONARY_RATIO);
+    if samples.len() < DICTIONARY_MIN_SAMPLES || size < DICTIONARY_MIN_SIZE {
+        return Ok(None);
+    }
+
+    match zstd::dict::from_samples(&samples, size) {
+        Ok(dictionary) => {
+            if context.verbose {
+                println!(
+                    "Trained a {} byte dictionary from {} files",
+                    dictionary.len(),
+                    samples.len()
+                )
+            }
+            Ok(Some(dictionary))
+        }
+        Err(err) => {
+            if context.verbose {
+                println!("Skipping dictionary ({})", err)
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn compress(
+    content: &[u8],
+    context: &Context,
+    level: u32,
+    dictionary: Option<&EncoderDictionary>,
+    eval_context: &EvalContext,
+) -> meta::error::Result<(Compression, Attributes, Vec<u8>)> {
+    match context.compression {
+        Compression::None => Ok((Compression::None, Attributes::default(), content.to_vec())),
+        Compression::Gzip => {
+            let mut buff = Vec::<u8>::new();
+            let mut meow = GzEncoder::new(content, flate2::Compression::new(level));
+            wrap_context(
+                meow.read_to_end(&mut buff),
+                eval_context.push("gzip".to_string()),
+                CatError::FailedToCompressData,
+            )?;
+            Ok((Compression::Gzip, Attributes::default(), buff))
+        }
+        Compression::Zstd => {
+            let eval_context = eval_context.push("zstd".to_string());
+            let (buff, attributes) = match dictionary {
+                Some(dictionary) => (
+                    wrap_context(
+                        zstd::bulk::Compressor::with_prepared_dictionary(dictionary)
+                            .and_then(|mut compressor| compressor.compress(content)),
+                        eval_context,
+                        CatError::FailedToCompressData,
+                    )?,
+                    Attributes {
+                        dictionary: Some(0),
+                        ..Default::default()
+                    },
+                ),
+                None => (
+                    wrap_context(
+                        zstd::bulk::compress(content, level as i32),
+                        eval_context,
+                        CatError::FailedToCompressData,
+                    )?,
+                    Attributes::default(),
+                ),
+            };
+            Ok((Compression::Zstd, attributes, buff))
+        }
     }
+}
 
-    panic!("Failed to create entry {}", path.display());
+fn create_entry<'a>(node: Node, files: &mut impl Iterator<Item = &'a EntryData>) -> Entry {
+    match node {
+        Node::Directory {
+            name,
+            children,
+            attributes,
+        } => Entry::Directory {
+            name,
+            entries: children
+                .into_iter()
+                .map(|node| create_entry(node, files))
+                .collect(),
+            attributes,
+        },
+        Node::File { name, attributes, .. } => {
+            let data = files.next().expect("Expected a blob for every file");
+            Entry::File {
+                name,
+                offset: data.offset,
+                size: data.size,
+                compression: data.compression.clone(),
+                attributes: Attributes {
+                    mode: attributes.mode,
+                    modified: attributes.modified,
+                    ..data.attributes.clone()
+                },
+            }
+        }
+        Node::Symlink { name, target } => Entry::Symlink {
+            name,
+            target,
+            attributes: Attributes::default(),
+        },
+    }
 }
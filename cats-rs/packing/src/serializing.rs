@@ -1,17 +1,23 @@
 use std::io::Write;
-use meta::metadata::{Compression, Entry, Header};
+use meta::metadata::{
+    Attributes, Block, Chunk, Compression, Dictionary, Encryption, Entry, Header, ATTRIBUTE_BLOCK,
+    ATTRIBUTE_CHECKSUM, ATTRIBUTE_CHUNKS, ATTRIBUTE_DICTIONARY, ATTRIBUTE_MODE, ATTRIBUTE_MODIFIED, ENCRYPTION_ARGON2ID_XCHACHA20POLY1305,
+    SECTION_AUTHENTICATION, SECTION_BLOCKS, SECTION_CHUNKS, SECTION_DICTIONARIES, SECTION_ENCRYPTION,
+    SECTION_SEALED_HEADER,
+};
 use meta::error::CatError;
 use meta::utils::EvalContext;
-use meta::utils::{wrap_context, write_string, write_u16, write_u32};
+use meta::utils::{wrap_context, write_long_string, write_string, write_u16, write_u32};
 
 pub trait CatSerializable {
-    fn serialize(&self, writer: &mut impl Write, context: EvalContext) -> meta::error::Result<()>;
+    fn serialize(&self, writer: &mut impl Write, version: u8, context: EvalContext) -> meta::error::Result<()>;
 }
 
 impl CatSerializable for Compression {
-    fn serialize(&self, writer: &mut impl Write, context: EvalContext) -> meta::error::Result<()> {
+    fn serialize(&self, writer: &mut impl Write, _: u8, context: EvalContext) -> meta::error::Result<()> {
         wrap_context(
             match self {
+                Compression::Zstd => writer.write(&[0xFDu8]),
                 Compression::Gzip => writer.write(&[0xFEu8]),
                 Compression::None => writer.write(&[0xFFu8]),
             },
@@ -23,10 +29,135 @@ impl CatSerializable for Compression {
     }
 }
 
+impl CatSerializable for Attributes {
+    fn serialize(&self, writer: &mut impl Write, _: u8, context: EvalContext) -> meta::error::Result<()> {
+        let mut attributes = Vec::<(u8, Vec<u8>)>::new();
+        if let Some(dictionary) = self.dictionary {
+            attributes.push((ATTRIBUTE_DICTIONARY, u16::to_be_bytes(dictionary).to_vec()));
+        }
+        if let Some(block) = self.block {
+            attributes.push((ATTRIBUTE_BLOCK, u32::to_be_bytes(block).to_vec()));
+        }
+        if let Some(chunks) = &self.chunks {
+            attributes.push((
+                ATTRIBUTE_CHUNKS,
+                chunks.iter().flat_map(|chunk| u32::to_be_bytes(*chunk)).collect(),
+            ));
+        }
+        if let Some(checksum) = self.checksum {
+            attributes.push((ATTRIBUTE_CHECKSUM, u32::to_be_bytes(checksum).to_vec()));
+        }
+        if let Some(mode) = self.mode {
+            attributes.push((ATTRIBUTE_MODE, u16::to_be_bytes(mode).to_vec()));
+        }
+        if let Some(modified) = self.modified {
+            attributes.push((ATTRIBUTE_MODIFIED, i64::to_be_bytes(modified).to_vec()));
+        }
+
+        wrap_context(
+            writer.write(&[attributes.len() as u8]),
+            context.push("attribute count".to_string()),
+            CatError::ErrorWritingMetadata,
+        )?;
+        for (key, value) in attributes {
+            write_block(writer, key, &value, context.push(format!("attribute {key}")))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CatSerializable for Vec<Dictionary> {
+    fn serialize(&self, writer: &mut impl Write, _: u8, context: EvalContext) -> meta::error::Result<()> {
+        wrap_context(
+            write_u16(
+                &wrap_context(
+                    u16::try_from(self.len()),
+                    context.push("dictionary count".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?,
+                writer,
+            ),
+            context.push("dictionary count".to_string()),
+            CatError::ErrorWritingMetadata,
+        )?;
+        for dictionary in self {
+            wrap_context(
+                write_u32(&dictionary.offset, writer)
+                    .and_then(|_| write_u32(&dictionary.size, writer)),
+                context.push("dictionary".to_string()),
+                CatError::ErrorWritingMetadata,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CatSerializable for Vec<Block> {
+    fn serialize(&self, writer: &mut impl Write, version: u8, context: EvalContext) -> meta::error::Result<()> {
+        wrap_context(
+            write_u32(
+                &wrap_context(
+                    u32::try_from(self.len()),
+                    context.push("block count".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?,
+                writer,
+            ),
+            context.push("block count".to_string()),
+            CatError::ErrorWritingMetadata,
+        )?;
+        for (i, block) in self.iter().enumerate() {
+            let context = context.push(i.to_string());
+            wrap_context(
+                write_u32(&block.offset, writer).and_then(|_| write_u32(&block.size, writer)),
+                context.clone(),
+                CatError::ErrorWritingMetadata,
+            )?;
+            block.compression.serialize(writer, version, context.push("compression".to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CatSerializable for Vec<Chunk> {
+    fn serialize(&self, writer: &mut impl Write, version: u8, context: EvalContext) -> meta::error::Result<()> {
+        wrap_context(
+            write_u32(
+                &wrap_context(
+                    u32::try_from(self.len()),
+                    context.push("chunk count".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?,
+                writer,
+            ),
+            context.push("chunk count".to_string()),
+            CatError::ErrorWritingMetadata,
+        )?;
+        for (i, chunk) in self.iter().enumerate() {
+            let context = context.push(i.to_string());
+            wrap_context(
+                write_u32(&chunk.offset, writer).and_then(|_| write_u32(&chunk.size, writer)),
+                context.clone(),
+                CatError::ErrorWritingMetadata,
+            )?;
+            chunk.compression.serialize(writer, version, context.push("compression".to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
 impl CatSerializable for Entry {
-    fn serialize(&self, buffer: &mut impl Write, context: EvalContext) -> meta::error::Result<()> {
+    fn serialize(&self, buffer: &mut impl Write, version: u8, context: EvalContext) -> meta::error::Result<()> {
         match self {
-            Entry::Directory { name, entries } => {
+            Entry::Directory {
+                name,
+                entries,
+                attributes,
+            } => {
                 wrap_context(
                     buffer.write(&[1]),
                     context.push("entry type".to_string()),
@@ -37,6 +168,9 @@ impl CatSerializable for Entry {
                     context.push("file name".to_string()),
                     CatError::ErrorWritingMetadata,
                 )?;
+                if version >= 2 {
+                    attributes.serialize(buffer, version, context.push(name.to_string()))?;
+                }
                 wrap_context(
                     write_u16(
                         &wrap_context(
@@ -50,7 +184,7 @@ impl CatSerializable for Entry {
                     CatError::ErrorWritingMetadata,
                 )?;
                 for x in entries {
-                    x.serialize(buffer, context.push(name.to_string()))?
+                    x.serialize(buffer, version, context.push(name.to_string()))?
                 }
             }
             Entry::File {
@@ -58,6 +192,7 @@ impl CatSerializable for Entry {
                 offset,
                 size,
                 compression,
+                attributes,
             } => {
                 wrap_context(
                     buffer.write(&[0]),
@@ -69,6 +204,9 @@ impl CatSerializable for Entry {
                     context.push("file name".to_string()),
                     CatError::ErrorWritingMetadata,
                 )?;
+                if version >= 2 {
+                    attributes.serialize(buffer, version, context.push(name.to_string()))?;
+                }
                 wrap_context(
                     write_u32(offset, buffer),
                     context.push("file offset".to_string()),
@@ -81,11 +219,36 @@ impl CatSerializable for Entry {
                 )?;
                 compression.serialize(
                     buffer,
+                    version,
                     context
                         .push(name.to_string())
                         .push("compression".to_string()),
                 )?;
             }
+            Entry::Symlink {
+                name,
+                target,
+                attributes,
+            } => {
+                wrap_context(
+                    buffer.write(&[2]),
+                    context.push("entry type".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?;
+                wrap_context(
+                    write_string(name, buffer),
+                    context.push("link name".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?;
+                if version >= 2 {
+                    attributes.serialize(buffer, version, context.push(name.to_string()))?;
+                }
+                wrap_context(
+                    write_long_string(target, buffer),
+                    context.push(name.to_string()).push("link target".to_string()),
+                    CatError::ErrorWritingMetadata,
+                )?;
+            }
         }
 
         Ok(())
@@ -93,13 +256,68 @@ impl CatSerializable for Entry {
 }
 
 
+impl CatSerializable for Encryption {
+    fn serialize(&self, writer: &mut impl Write, _: u8, context: EvalContext) -> meta::error::Result<()> {
+        wrap_context(
+            writer
+                .write_all(&[ENCRYPTION_ARGON2ID_XCHACHA20POLY1305])
+                .and_then(|_| writer.write_all(&self.salt))
+                .and_then(|_| write_u32(&self.memory, writer))
+                .and_then(|_| write_u32(&self.iterations, writer))
+                .and_then(|_| writer.write_all(&[self.parallelism])),
+            context,
+            CatError::ErrorWritingMetadata,
+        )
+    }
+}
+
 impl CatSerializable for Header {
-    fn serialize(&self, writer: &mut impl Write, context: EvalContext) -> meta::error::Result<()> {
+    fn serialize(&self, writer: &mut impl Write, _: u8, context: EvalContext) -> meta::error::Result<()> {
+        let version = self.version;
         wrap_context(
-            writer.write(&[self.version]),
+            writer.write(&[version]),
             context.push("version".to_string()),
             CatError::ErrorWritingMetadata,
         )?;
+        if version >= 2 {
+            let mut sections = Vec::<(u8, Vec<u8>)>::new();
+            // Comes first, so readers find the tag at a fixed position to check it.
+            if let Some(authentication) = &self.authentication {
+                sections.push((SECTION_AUTHENTICATION, authentication.clone()));
+            }
+            if !self.dictionaries.is_empty() {
+                let mut section = Vec::<u8>::new();
+                self.dictionaries.serialize(&mut section, version, context.push("dictionaries".to_string()))?;
+                sections.push((SECTION_DICTIONARIES, section));
+            }
+            if !self.blocks.is_empty() {
+                let mut section = Vec::<u8>::new();
+                self.blocks.serialize(&mut section, version, context.push("blocks".to_string()))?;
+                sections.push((SECTION_BLOCKS, section));
+            }
+            if !self.chunks.is_empty() {
+                let mut section = Vec::<u8>::new();
+                self.chunks.serialize(&mut section, version, context.push("chunks".to_string()))?;
+                sections.push((SECTION_CHUNKS, section));
+            }
+            if let Some(encryption) = &self.encryption {
+                let mut section = Vec::<u8>::new();
+                encryption.serialize(&mut section, version, context.push("encryption".to_string()))?;
+                sections.push((SECTION_ENCRYPTION, section));
+            }
+            if let Some(sealed) = &self.sealed {
+                sections.push((SECTION_SEALED_HEADER, sealed.clone()));
+            }
+
+            wrap_context(
+                writer.write(&[sections.len() as u8]),
+                context.push("section count".to_string()),
+                CatError::ErrorWritingMetadata,
+            )?;
+            for (key, section) in sections {
+                write_block(writer, key, &section, context.push(format!("section {key}")))?;
+            }
+        }
         wrap_context(
             write_u16(
                 &wrap_context(
@@ -113,8 +331,25 @@ impl CatSerializable for Header {
             CatError::ErrorWritingMetadata,
         )?;
         for entries in &self.entries {
-            entries.serialize(writer, context.push("head".to_string()))?
+            entries.serialize(writer, version, context.push("head".to_string()))?
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Writes a key followed by the length prefixed value, used for both sections and attributes.
+fn write_block(writer: &mut impl Write, key: u8, value: &[u8], context: EvalContext) -> meta::error::Result<()> {
+    let length = wrap_context(
+        u32::try_from(value.len()),
+        context.clone(),
+        CatError::ErrorWritingMetadata,
+    )?;
+    wrap_context(
+        writer
+            .write_all(&[key])
+            .and_then(|_| write_u32(&length, writer))
+            .and_then(|_| writer.write_all(value)),
+        context,
+        CatError::ErrorWritingMetadata,
+    )
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use ed25519_dalek::{Signer, SigningKey};
+use meta::error::CatError;
+use meta::metadata::{Signature, MAGIC_NUMBER};
+use unpacking::signature::strip_signature;
+use crate::packing::replace_file;
+
+/// Loads an Ed25519 private key from a PKCS#8 PEM file, as written by `openssl genpkey -algorithm ed25519`.
+pub fn load_signing_key(path: &Path) -> meta::error::Result<SigningKey> {
+    let pem = fs::read_to_string(path).map_err(|err| {
+        CatError::FailedToOpenInput {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        }
+    })?;
+    SigningKey::from_pkcs8_pem(&pem).map_err(|err| {
+        CatError::InvalidKey {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        }
+    })
+}
+
+/// Signs the header and data of an archive, replacing the signature it already has. Only a trailer
+/// that is valid for its own key counts as a signature, anything else is signed along with the data.
+pub fn sign(archive: &Path, key: &SigningKey) -> meta::error::Result<()> {
+    let mut content = fs::read(archive).map_err(|err| {
+        CatError::ErrorReadingFile {
+            path: archive.display().to_string(),
+            error: err.to_string(),
+        }
+    })?;
+    if !content.starts_with(&MAGIC_NUMBER) {
+        return CatError::InvalidFileType.into();
+    }
+
+    strip_signature(&mut content);
+    let signature = Signature {
+        public_key: key.verifying_key().to_bytes(),
+        signature: key.sign(&content).to_bytes(),
+    };
+    content.extend(signature.to_bytes());
+
+    replace_file(archive, |file| {
+        file.write_all(&content).map_err(|err| {
+            CatError::ErrorWritingFile {
+                path: archive.display().to_string(),
+                error: err.to_string(),
+            }
+        })
+    })
+}
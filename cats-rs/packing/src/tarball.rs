@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use flate2::read::GzDecoder;
+use meta::error::CatError;
+use meta::Context;
+use tar::{Archive, EntryType};
+use crate::converting::{create_nodes, find, insert, map_entry, map_path, map_target, Tree};
+use crate::packing::{stored_attributes, write_archive, Content};
+
+/// Gzip streams start with these two bytes.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Packs the contents of a tar stream, which may be gzip compressed. The stream is read once from
+/// start to end, so it can come from a pipe, and the files are spooled to a directory next to the
+/// target until they are packed. Names CATS doesn't allow are mapped to allowed ones, and entries
+/// that can't be represented are skipped, both are reported on stderr.
+pub fn pack_tar(source: impl Read, name: &str, target: &Path, context: &Context) -> meta::error::Result<()> {
+    let error = |err: std::io::Error| CatError::ErrorReadingFile {
+        path: name.to_string(),
+        error: err.to_string(),
+    };
+    let mut source = BufReader::new(source);
+    let gzip = source.fill_buf().map_err(error)?.starts_with(&GZIP_MAGIC);
+    let source: Box<dyn Read> = match gzip {
+        true => Box::new(GzDecoder::new(source)),
+        false => Box::new(source),
+    };
+
+    let mut spool = Spool::new(target)?;
+    let mut root = BTreeMap::<String, Tree>::new();
+    let mut archive = Archive::new(source);
+    for entry in archive.entries().map_err(error)? {
+        let mut entry = entry.map_err(error)?;
+        let raw = entry.path().map_err(error)?.to_string_lossy().into_owned();
+        let Some(names) = map_entry(&raw, context) else {
+            continue;
+        };
+
+        let header = entry.header();
+        let attributes = stored_attributes(
+            header.mode().ok().map(|mode| mode as u16),
+            header.mtime().ok().map(|mtime| mtime as i64),
+            context,
+        );
+        let tree = match header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => Tree::File {
+                content: Content::Spooled(spool.write(&mut entry)?),
+                gzip: None,
+                attributes,
+            },
+            EntryType::Directory => Tree::Directory(BTreeMap::new(), attributes),
+            EntryType::Symlink | EntryType::Link => {
+                let target = match entry.link_name().map_err(error)? {
+                    Some(target) => target.to_string_lossy().into_owned(),
+                    None => {
+                        eprintln!("Skipping {raw} as it has no link target");
+                        continue;
+                    }
+                };
+                match header.entry_type() {
+                    // Targets are mapped like names, so links to renamed entries still resolve.
+                    EntryType::Symlink => Tree::Symlink(map_target(&target)),
+                    // Hard links become copies of the file, which dedup stores only once.
+                    _ => match map_path(&target).and_then(|names| find(&root, &names)) {
+                        Some(Tree::File {
+                            content, attributes, ..
+                        }) => Tree::File {
+                            content: content.clone(),
+                            gzip: None,
+                            attributes: attributes.clone(),
+                        },
+                        _ => {
+                            eprintln!("Skipping {raw} as the file it links to wasn't found");
+                            continue;
+                        }
+                    },
+                }
+            }
+            entry_type => {
+                eprintln!("Skipping {raw} as {entry_type:?} entries aren't supported");
+                continue;
+            }
+        };
+        insert(&mut root, &names, tree);
+    }
+
+    write_archive(target, context, || Ok(create_nodes(root, Path::new(""))))
+}
+
+/// A directory holding the contents of the files read from a stream until they are packed, so
+/// they don't have to be kept in memory. It is removed again when dropped.
+struct Spool {
+    directory: PathBuf,
+    count: usize,
+}
+
+impl Spool {
+    fn new(target: &Path) -> meta::error::Result<Spool> {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let directory = target.with_file_name(format!(".{name}.{}.spool", std::process::id()));
+        fs::create_dir(&directory).map_err(|err| CatError::ErrorWritingFile {
+            path: directory.display().to_string(),
+            error: err.to_string(),
+        })?;
+        Ok(Spool { directory, count: 0 })
+    }
+
+    /// Copies the content into a file of its own and returns its path.
+    fn write(&mut self, content: &mut impl Read) -> meta::error::Result<PathBuf> {
+        let path = self.directory.join(self.count.to_string());
+        self.count += 1;
+        File::create(&path)
+            .and_then(|mut file| std::io::copy(content, &mut file))
+            .map_err(|err| CatError::ErrorWritingFile {
+                path: path.display().to_string(),
+                error: err.to_string(),
+            })?;
+        Ok(path)
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use flate2::read::DeflateDecoder;
+use flate2::Crc;
+use meta::error::CatError;
+use meta::utils::unix_time;
+use meta::Context;
+use zip::read::ZipFile;
+use zip::{CompressionMethod, ExtraField, ZipArchive};
+use crate::converting::{create_nodes, insert, map_entry, map_target, Tree};
+use crate::packing::{stored_attributes, write_archive, Content};
+
+/// Packs the contents of a zip archive. Deflate compressed files are wrapped into gzip members
+/// instead of being decompressed and compressed again, as long as gzip is wanted at its default
+/// level. Names CATS doesn't allow are mapped to allowed ones, and entries that can't be
+/// represented are skipped, both are reported on stderr.
+pub fn pack_zip(source: &Path, target: &Path, context: &Context) -> meta::error::Result<()> {
+    let file = File::open(source).map_err(|err| CatError::FailedToOpenInput {
+        path: source.display().to_string(),
+        error: err.to_string(),
+    })?;
+    let error = |err: &dyn std::error::Error| CatError::ErrorReadingFile {
+        path: source.display().to_string(),
+        error: err.to_string(),
+    };
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| error(&err))?;
+
+    let mut root = BTreeMap::<String, Tree>::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index_raw(index).map_err(|err| error(&err))?;
+        let raw = file.name().to_string();
+        let Some(names) = map_entry(&raw, context) else {
+            continue;
+        };
+        if file.encrypted() {
+            eprintln!("Skipping {raw} as it is encrypted");
+            continue;
+        }
+
+        let attributes = stored_attributes(file.unix_mode().map(|mode| mode as u16), modified(&file), context);
+        if file.is_dir() {
+            insert(&mut root, &names, Tree::Directory(BTreeMap::new(), attributes));
+            continue;
+        }
+
+        // The sizes in the headers aren't trusted, the vectors grow with what is actually read.
+        let mut stored = Vec::<u8>::new();
+        file.read_to_end(&mut stored).map_err(|err| error(&err))?;
+        let (content, gzip) = match file.compression() {
+            CompressionMethod::Stored => (stored, None),
+            CompressionMethod::Deflated => {
+                let mut content = Vec::<u8>::new();
+                DeflateDecoder::new(stored.as_slice())
+                    .read_to_end(&mut content)
+                    .map_err(|err| error(&err))?;
+                let gzip = wrap_gzip(&stored, file.crc32(), content.len() as u32);
+                (content, Some(gzip))
+            }
+            method => {
+                eprintln!("Skipping {raw} as {method:?} compression isn't supported");
+                continue;
+            }
+        };
+
+        let mut crc = Crc::new();
+        crc.update(&content);
+        let mismatch = match (crc.sum() == file.crc32(), content.len() as u64 == file.size()) {
+            (false, _) => Some("checksum mismatch"),
+            (true, false) => Some("size mismatch"),
+            (true, true) => None,
+        };
+        if let Some(mismatch) = mismatch {
+            return Err(CatError::ErrorReadingFile {
+                path: format!("{}/{raw}", source.display()),
+                error: mismatch.to_string(),
+            });
+        }
+
+        let tree = match file.is_symlink() {
+            true => Tree::Symlink(map_target(&String::from_utf8_lossy(&content))),
+            false => Tree::File {
+                content: Content::Memory(content),
+                gzip,
+                attributes,
+            },
+        };
+        insert(&mut root, &names, tree);
+    }
+
+    write_archive(target, context, || Ok(create_nodes(root, Path::new(""))))
+}
+
+/// The modification time from the extended timestamp field, falling back to the local MS-DOS time
+/// every entry has, which is taken as UTC.
+fn modified<R: Read>(file: &ZipFile<R>) -> Option<i64> {
+    let extended = file.extra_data_fields().find_map(|field| match field {
+        ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+        _ => None,
+    });
+    match extended {
+        Some(modified) => Some(modified as i64),
+        None => file.last_modified().map(|time| {
+            unix_time(
+                time.year() as i64,
+                time.month(),
+                time.day(),
+                time.hour(),
+                time.minute(),
+                time.second(),
+            )
+        }),
+    }
+}
+
+/// Wraps raw deflate data into a gzip member, a 10 byte header in front and the CRC32 and size of
+/// the content behind it.
+fn wrap_gzip(deflate: &[u8], crc: u32, size: u32) -> Vec<u8> {
+    let mut gzip = Vec::<u8>::with_capacity(deflate.len() + 18);
+    gzip.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff]);
+    gzip.extend_from_slice(deflate);
+    gzip.extend_from_slice(&crc.to_le_bytes());
+    gzip.extend_from_slice(&size.to_le_bytes());
+    gzip
+}
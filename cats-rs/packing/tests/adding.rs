@@ -0,0 +1,64 @@
+mod common;
+
+use common::{encrypted, packed, read, write};
+use ed25519_dalek::SigningKey;
+use meta::error::CatError;
+use meta::metadata::Entry;
+use meta::Context;
+use packing::editing::add;
+use packing::manifest::Manifest;
+use packing::signing::sign;
+use unpacking::reader::CatReader;
+
+#[test]
+fn add_files() {
+    for (name, context) in [("add", Context::default()), ("add-encrypted", encrypted())] {
+        let (scratch, mut files) = packed(name, &context);
+        write(&scratch.join("extra/new.txt"), b"new");
+        write(&scratch.join("extra/a.txt"), b"replaced");
+
+        let manifest = Manifest::from_text(&format!(
+            "new/new.txt {}\na.txt {}",
+            scratch.join("extra/new.txt").display(),
+            scratch.join("extra/a.txt").display()
+        ))
+        .unwrap();
+        add(&scratch.join("archive.cats"), &manifest, &context).unwrap();
+
+        files.insert("new/new.txt".to_string(), b"new".to_vec());
+        files.insert("a.txt".to_string(), b"replaced".to_vec());
+        assert_eq!(read(&scratch.join("archive.cats"), &context), files);
+    }
+}
+
+#[test]
+fn add_with_password_to_plain_archive() {
+    let (scratch, files) = packed("add-password", &Context::default());
+    write(&scratch.join("extra/new.txt"), b"new");
+
+    let manifest = Manifest::from_text(&format!("new.txt {}", scratch.join("extra/new.txt").display())).unwrap();
+    let result = add(&scratch.join("archive.cats"), &manifest, &encrypted());
+    assert!(matches!(result, Err(CatError::UnexpectedPassword)));
+    assert_eq!(read(&scratch.join("archive.cats"), &Context::default()), files);
+}
+
+#[test]
+fn add_to_signed_archive() {
+    let (scratch, mut files) = packed("add-signed", &Context::default());
+    sign(&scratch.join("archive.cats"), &SigningKey::from_bytes(&[7; 32])).unwrap();
+    write(&scratch.join("extra/copy.txt"), b"hello");
+
+    let manifest = Manifest::from_text(&format!("copy.txt {}", scratch.join("extra/copy.txt").display())).unwrap();
+    add(&scratch.join("archive.cats"), &manifest, &Context::default()).unwrap();
+
+    // The signature is dropped and the new file points to the stored copy of its content.
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert_eq!(reader.data_size(), reader.header().data_end());
+    let location = |path: &str| match reader.entry(path) {
+        Some(Entry::File { offset, size, .. }) => (*offset, *size),
+        _ => panic!("Expected {path} to be a file"),
+    };
+    assert_eq!(location("copy.txt"), location("a.txt"));
+    files.insert("copy.txt".to_string(), b"hello".to_vec());
+    assert_eq!(read(&scratch.join("archive.cats"), &Context::default()), files);
+}
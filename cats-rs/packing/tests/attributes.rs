@@ -0,0 +1,73 @@
+mod common;
+
+use std::fs::{self, File};
+use std::time::{Duration, UNIX_EPOCH};
+use common::{packed, round_trip, write, Scratch};
+use meta::metadata::Entry;
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+use unpacking::unpacking::unpack;
+
+#[test]
+fn not_stored_by_default() {
+    let reader = round_trip("attributes-default", &Context::default());
+    assert_eq!(reader.header().version, 1);
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert!(attributes.is_empty());
+}
+
+#[test]
+fn stored_when_asked_for() {
+    let context = Context {
+        permissions: true,
+        times: true,
+        ..Default::default()
+    };
+    let (scratch, _) = packed("attributes", &context);
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert_eq!(reader.header().version, 2);
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert!(attributes.mode.is_some());
+    assert!(attributes.modified.is_some());
+}
+
+#[test]
+#[cfg(unix)]
+fn restored_when_unpacking() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new("attributes-restore");
+    write(&scratch.join("source/locked/a.txt"), b"hello");
+    let modified = UNIX_EPOCH + Duration::from_secs(1704103203);
+    File::options()
+        .write(true)
+        .open(scratch.join("source/locked/a.txt"))
+        .and_then(|file| file.set_modified(modified))
+        .unwrap();
+    fs::set_permissions(scratch.join("source/locked/a.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+    // A parent without write access, so its children have to be restored before it.
+    fs::set_permissions(scratch.join("source/locked"), fs::Permissions::from_mode(0o555)).unwrap();
+
+    let context = Context {
+        permissions: true,
+        times: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+    unpack(&scratch.join("target"), &scratch.join("archive.cats"), &context).unwrap();
+
+    let metadata = fs::metadata(scratch.join("target/locked/a.txt")).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    assert_eq!(metadata.modified().unwrap(), modified);
+    let metadata = fs::metadata(scratch.join("target/locked")).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o555);
+
+    for directory in [scratch.join("source/locked"), scratch.join("target/locked")] {
+        fs::set_permissions(directory, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+use common::{round_trip, write, Scratch};
+use meta::error::CatError;
+use meta::metadata::{Compression, Entry};
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+
+#[test]
+fn stored_checksums() {
+    let context = Context {
+        checksums: true,
+        ..Default::default()
+    };
+    let reader = round_trip("checksums", &context);
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert_eq!(attributes.checksum, Some(crc32c::crc32c(b"hello")));
+}
+
+#[test]
+fn checksums_off_by_default() {
+    let reader = round_trip("no-checksums", &Context::default());
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert_eq!(attributes.checksum, None);
+}
+
+#[test]
+fn corrupted_content() {
+    let scratch = Scratch::new("checksum-mismatch");
+    write(&scratch.join("source/a.txt"), b"hello");
+    let context = Context {
+        compression: Compression::None,
+        checksums: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+
+    let mut content = fs::read(scratch.join("archive.cats")).unwrap();
+    let last = content.len() - 1;
+    content[last] = b'!';
+    fs::write(scratch.join("archive.cats"), content).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert!(matches!(reader.read("a.txt"), Err(CatError::ChecksumMismatch { .. })));
+}
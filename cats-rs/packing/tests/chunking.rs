@@ -0,0 +1,16 @@
+mod common;
+
+use common::round_trip;
+use meta::Context;
+
+#[test]
+fn shared_chunks() {
+    let context = Context {
+        chunk_size: 4096,
+        ..Default::default()
+    };
+    let reader = round_trip("chunking", &context);
+    // The second large file repeats the first one, so most of its chunks are shared.
+    let stored = reader.header().chunks.iter().map(|chunk| chunk.size as usize).sum::<usize>();
+    assert!(stored < 300 * 1024);
+}
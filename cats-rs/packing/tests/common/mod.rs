@@ -0,0 +1,144 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use meta::metadata::Entry;
+use meta::utils::EvalContext;
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+
+/// A directory under the system temp directory that is removed again when dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Scratch {
+        let path = std::env::temp_dir().join(format!("cats-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Scratch(path)
+    }
+
+    pub fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn write(path: &Path, content: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// Deterministic bytes that don't compress.
+pub fn noise(length: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed | 1;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// Fills the directory with a mix of small similar files, duplicates, an empty file and larger
+/// incompressible files sharing most of their content.
+pub fn create_tree(root: &Path) {
+    write(&root.join("a.txt"), b"hello");
+    write(&root.join("empty"), b"");
+    for index in 0..300 {
+        let json = format!(
+            r#"{{"id": {index}, "name": "item-{index}", "tags": ["model", "texture", "block"], "weight": {}}}"#,
+            index * 7 % 13
+        );
+        write(&root.join(format!("data/{index}.json")), json.as_bytes());
+    }
+    write(&root.join("data/copy.json"), &fs::read(root.join("data/0.json")).unwrap());
+
+    let large = noise(256 * 1024, 1);
+    let mut changed = large.clone();
+    changed.extend(noise(1024, 2));
+    write(&root.join("large/a.bin"), &large);
+    write(&root.join("large/b.bin"), &changed);
+}
+
+/// Every file below the directory by its `/` separated path.
+pub fn read_tree(root: &Path) -> BTreeMap<String, Vec<u8>> {
+    fn walk(directory: &Path, prefix: &str, files: &mut BTreeMap<String, Vec<u8>>) {
+        for entry in fs::read_dir(directory).unwrap() {
+            let entry = entry.unwrap();
+            let path = format!("{prefix}{}", entry.file_name().to_str().unwrap());
+            match entry.file_type().unwrap().is_dir() {
+                true => walk(&entry.path(), &format!("{path}/"), files),
+                false => {
+                    files.insert(path, fs::read(entry.path()).unwrap());
+                }
+            }
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    walk(root, "", &mut files);
+    files
+}
+
+/// Every file of the archive by its `/` separated path.
+pub fn read_archive(reader: &CatReader) -> BTreeMap<String, Vec<u8>> {
+    fn walk(reader: &CatReader, entries: &[Entry], prefix: &str, files: &mut BTreeMap<String, Vec<u8>>) {
+        for entry in entries {
+            let path = format!("{prefix}{}", entry.name());
+            match entry {
+                Entry::Directory { entries, .. } => walk(reader, entries, &format!("{path}/"), files),
+                Entry::File { .. } => {
+                    let content = reader.read_entry(entry, &EvalContext::new(path.clone())).unwrap();
+                    files.insert(path, content);
+                }
+                Entry::Symlink { .. } => {}
+            }
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    walk(reader, &reader.header().entries, "", &mut files);
+    files
+}
+
+/// Packs the test tree with the context and checks every file reads back unchanged.
+pub fn round_trip(name: &str, context: &Context) -> CatReader {
+    let scratch = Scratch::new(name);
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), context).unwrap();
+
+    let reader = CatReader::open_with(&scratch.join("archive.cats"), context).unwrap();
+    assert_eq!(read_archive(&reader), read_tree(&scratch.join("source")));
+    reader
+}
+
+pub fn encrypted() -> Context {
+    Context {
+        password: Some("secret".to_string()),
+        ..Default::default()
+    }
+}
+
+/// Packs the test tree and returns its files along with the scratch directory holding the archive.
+pub fn packed(name: &str, context: &Context) -> (Scratch, BTreeMap<String, Vec<u8>>) {
+    let scratch = Scratch::new(name);
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), context).unwrap();
+    let files = read_tree(&scratch.join("source"));
+    (scratch, files)
+}
+
+/// Every file of the archive at the path, opened with the context.
+pub fn read(path: &Path, context: &Context) -> BTreeMap<String, Vec<u8>> {
+    read_archive(&CatReader::open_with(path, context).unwrap())
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{round_trip, Scratch};
+use meta::error::CatError;
+use meta::metadata::Compression;
+use meta::Context;
+use packing::packing::pack;
+
+#[test]
+fn uncompressed() {
+    let context = Context {
+        compression: Compression::None,
+        ..Default::default()
+    };
+    round_trip("uncompressed", &context);
+}
+
+#[test]
+fn gzip_levels() {
+    for level in [None, Some(1), Some(9)] {
+        let context = Context {
+            level,
+            ..Default::default()
+        };
+        round_trip(&format!("gzip-{level:?}"), &context);
+    }
+}
+
+#[test]
+fn zstd_levels() {
+    for level in [None, Some(1), Some(19)] {
+        let context = Context {
+            compression: Compression::Zstd,
+            level,
+            ..Default::default()
+        };
+        round_trip(&format!("zstd-{level:?}"), &context);
+    }
+}
+
+#[test]
+fn level_out_of_range() {
+    let scratch = Scratch::new("level-out-of-range");
+    common::write(&scratch.join("source/a.txt"), b"hello");
+    let context = Context {
+        level: Some(10),
+        ..Default::default()
+    };
+    let result = pack(&scratch.join("source"), &scratch.join("archive.cats"), &context);
+    assert!(matches!(result, Err(CatError::InvalidCompressionLevel(10))));
+}
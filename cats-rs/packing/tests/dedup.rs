@@ -0,0 +1,33 @@
+mod common;
+
+use common::{round_trip, write, Scratch};
+use meta::metadata::Entry;
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+
+fn offset(reader: &CatReader, path: &str) -> u32 {
+    match reader.entry(path) {
+        Some(Entry::File { offset, .. }) => *offset,
+        _ => panic!("Expected {path} to be a file"),
+    }
+}
+
+#[test]
+fn identical_files_stored_once() {
+    let reader = round_trip("dedup", &Context::default());
+    assert_eq!(offset(&reader, "data/0.json"), offset(&reader, "data/copy.json"));
+    assert_ne!(offset(&reader, "data/0.json"), offset(&reader, "data/1.json"));
+}
+
+#[test]
+fn same_size_different_content() {
+    let scratch = Scratch::new("dedup-same-size");
+    write(&scratch.join("source/a"), b"aaaa");
+    write(&scratch.join("source/b"), b"aaab");
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert_ne!(offset(&reader, "a"), offset(&reader, "b"));
+    assert_eq!(reader.read("b").unwrap(), b"aaab");
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::round_trip;
+use meta::metadata::{Compression, Entry};
+use meta::Context;
+
+#[test]
+fn trained_dictionary() {
+    let context = Context {
+        compression: Compression::Zstd,
+        dictionary_size: 16 * 1024,
+        ..Default::default()
+    };
+    let reader = round_trip("dictionary", &context);
+    assert_eq!(reader.header().dictionaries.len(), 1);
+    let Some(Entry::File { attributes, .. }) = reader.entry("data/1.json") else {
+        panic!("Expected data/1.json to be a file");
+    };
+    assert_eq!(attributes.dictionary, Some(0));
+}
+
+#[test]
+fn no_dictionary_for_gzip() {
+    let context = Context {
+        dictionary_size: 16 * 1024,
+        ..Default::default()
+    };
+    let reader = round_trip("dictionary-gzip", &context);
+    assert!(reader.header().dictionaries.is_empty());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::fs;
+use common::{create_tree, write, Scratch};
+use meta::Context;
+use packing::packing::pack;
+use unpacking::diff::{diff, Difference, Kind};
+use unpacking::reader::CatReader;
+
+#[test]
+fn differences() {
+    let scratch = Scratch::new("diff");
+    create_tree(&scratch.join("before"));
+    create_tree(&scratch.join("after"));
+    write(&scratch.join("after/a.txt"), b"changed");
+    fs::remove_file(scratch.join("after/empty")).unwrap();
+    fs::rename(scratch.join("after/large/a.bin"), scratch.join("after/moved.bin")).unwrap();
+    write(&scratch.join("after/new.txt"), b"new");
+    pack(&scratch.join("before"), &scratch.join("before.cats"), &Context::default()).unwrap();
+    pack(&scratch.join("after"), &scratch.join("after.cats"), &Context::default()).unwrap();
+
+    let before = CatReader::open(&scratch.join("before.cats")).unwrap();
+    let after = CatReader::open(&scratch.join("after.cats")).unwrap();
+    let differences = diff(&before, &after).unwrap();
+
+    let expected = [
+        Difference::Modified {
+            path: "a.txt".to_string(),
+            kind: Kind::File,
+        },
+        Difference::Removed {
+            path: "empty".to_string(),
+            kind: Kind::File,
+        },
+        Difference::Moved {
+            from: "large/a.bin".to_string(),
+            to: "moved.bin".to_string(),
+        },
+        Difference::Added {
+            path: "new.txt".to_string(),
+            kind: Kind::File,
+        },
+    ];
+    for difference in &expected {
+        assert!(differences.contains(difference), "Missing {difference:?} in {differences:?}");
+    }
+    assert_eq!(differences.len(), expected.len());
+}
+
+#[test]
+fn identical() {
+    let scratch = Scratch::new("diff-identical");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert!(diff(&reader, &reader).unwrap().is_empty());
+}
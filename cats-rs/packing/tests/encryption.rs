@@ -0,0 +1,97 @@
+mod common;
+
+use std::fs;
+use common::{create_tree, encrypted, round_trip, Scratch};
+use meta::error::CatError;
+use meta::metadata::Entry;
+use meta::{Context, Solid};
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+use unpacking::unpacking::unpack;
+
+#[test]
+fn encrypt() {
+    let context = Context {
+        solid: Solid::Directory,
+        chunk_size: 4096,
+        checksums: true,
+        ..encrypted()
+    };
+    let reader = round_trip("encrypt", &context);
+    assert!(reader.cipher().is_some());
+    assert!(!reader.is_sealed());
+    // Checksums would give away the content in the clear header.
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert_eq!(attributes.checksum, None);
+}
+
+#[test]
+fn encrypt_header() {
+    let context = Context {
+        encrypt_header: true,
+        checksums: true,
+        ..encrypted()
+    };
+    let reader = round_trip("encrypt-header", &context);
+    assert!(reader.is_sealed());
+    let Some(Entry::File { attributes, .. }) = reader.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert!(attributes.checksum.is_some());
+}
+
+#[test]
+fn missing_password() {
+    let scratch = Scratch::new("missing-password");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &encrypted()).unwrap();
+
+    let result = CatReader::open(&scratch.join("archive.cats"));
+    assert!(matches!(result, Err(CatError::MissingPassword)));
+}
+
+#[test]
+fn wrong_password() {
+    let scratch = Scratch::new("wrong-password");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &encrypted()).unwrap();
+
+    let context = Context {
+        password: Some("wrong".to_string()),
+        ..Default::default()
+    };
+    assert!(CatReader::open_with(&scratch.join("archive.cats"), &context).is_err());
+}
+
+#[test]
+fn tampered_header() {
+    let scratch = Scratch::new("tampered-header");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &encrypted()).unwrap();
+
+    let mut content = fs::read(scratch.join("archive.cats")).unwrap();
+    let position = content.windows(5).position(|window| window == b"a.txt").unwrap();
+    content[position] = b'b';
+    fs::write(scratch.join("archive.cats"), content).unwrap();
+
+    assert!(matches!(
+        CatReader::open_with(&scratch.join("archive.cats"), &encrypted()),
+        Err(CatError::UnableToDecrypt(_))
+    ));
+}
+
+#[test]
+fn tampered_data() {
+    let scratch = Scratch::new("tampered-data");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &encrypted()).unwrap();
+
+    let mut content = fs::read(scratch.join("archive.cats")).unwrap();
+    let last = content.len() - 1;
+    content[last] ^= 1;
+    fs::write(scratch.join("archive.cats"), content).unwrap();
+
+    assert!(unpack(&scratch.join("target"), &scratch.join("archive.cats"), &encrypted()).is_err());
+}
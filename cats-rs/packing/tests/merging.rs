@@ -0,0 +1,56 @@
+mod common;
+
+use common::{encrypted, packed, read, write};
+use meta::metadata::Entry;
+use meta::Context;
+use packing::editing::{merge, Conflict};
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+
+#[test]
+fn merge_archives() {
+    let context = encrypted();
+    let (scratch, mut files) = packed("merge", &context);
+    write(&scratch.join("other/a.txt"), b"other");
+    write(&scratch.join("other/only/c.txt"), b"c");
+    pack(&scratch.join("other"), &scratch.join("other.cats"), &Context::default()).unwrap();
+
+    let sources = [scratch.join("archive.cats"), scratch.join("other.cats")];
+    let result = merge(&scratch.join("merged.cats"), &sources, Conflict::Error, &context);
+    assert!(result.is_err());
+
+    merge(&scratch.join("merged.cats"), &sources, Conflict::Last, &context).unwrap();
+    files.insert("a.txt".to_string(), b"other".to_vec());
+    files.insert("only/c.txt".to_string(), b"c".to_vec());
+    assert_eq!(read(&scratch.join("merged.cats"), &context), files);
+
+    merge(&scratch.join("first.cats"), &sources, Conflict::First, &context).unwrap();
+    files.insert("a.txt".to_string(), b"hello".to_vec());
+    assert_eq!(read(&scratch.join("first.cats"), &context), files);
+}
+
+#[test]
+fn merge_keeps_checksums_of_duplicates() {
+    let (scratch, mut files) = packed("merge-checksums", &Context::default());
+    write(&scratch.join("other/copy.txt"), b"hello");
+    let checksums = Context {
+        checksums: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("other"), &scratch.join("other.cats"), &checksums).unwrap();
+
+    let sources = [scratch.join("archive.cats"), scratch.join("other.cats")];
+    merge(&scratch.join("merged.cats"), &sources, Conflict::Error, &Context::default()).unwrap();
+    files.insert("copy.txt".to_string(), b"hello".to_vec());
+    assert_eq!(read(&scratch.join("merged.cats"), &Context::default()), files);
+
+    // The copy points to the content stored for a.txt, which has no checksum of its own.
+    let reader = CatReader::open(&scratch.join("merged.cats")).unwrap();
+    let (Some(Entry::File { offset, attributes, .. }), Some(Entry::File { offset: first, .. })) =
+        (reader.entry("copy.txt"), reader.entry("a.txt"))
+    else {
+        panic!("Expected files");
+    };
+    assert_eq!(offset, first);
+    assert_eq!(attributes.checksum, Some(crc32c::crc32c(b"hello")));
+}
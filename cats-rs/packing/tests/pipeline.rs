@@ -0,0 +1,21 @@
+mod common;
+
+use std::fs;
+use common::{create_tree, Scratch};
+use meta::Context;
+use packing::packing::pack;
+
+#[test]
+fn output_independent_of_jobs() {
+    let scratch = Scratch::new("jobs");
+    create_tree(&scratch.join("source"));
+    for jobs in [1, 4] {
+        let context = Context {
+            jobs,
+            ..Default::default()
+        };
+        pack(&scratch.join("source"), &scratch.join(&format!("{jobs}.cats")), &context).unwrap();
+    }
+
+    assert_eq!(fs::read(scratch.join("1.cats")).unwrap(), fs::read(scratch.join("4.cats")).unwrap());
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::fs;
+use common::{encrypted, packed, read};
+use meta::error::CatError;
+use meta::Context;
+use packing::editing::remove;
+
+#[test]
+fn remove_files() {
+    for compact in [false, true] {
+        let context = Context {
+            chunk_size: 4096,
+            ..encrypted()
+        };
+        let (scratch, mut files) = packed(&format!("remove-{compact}"), &context);
+        let before = fs::metadata(scratch.join("archive.cats")).unwrap().len();
+
+        let patterns = ["data/1*.json".to_string(), "large".to_string()];
+        remove(&scratch.join("archive.cats"), &patterns, compact, &context).unwrap();
+
+        files.retain(|path, _| !path.starts_with("data/1") && !path.starts_with("large/"));
+        assert_eq!(read(&scratch.join("archive.cats"), &context), files);
+        let after = fs::metadata(scratch.join("archive.cats")).unwrap().len();
+        assert_eq!(after < before / 2, compact);
+    }
+}
+
+#[test]
+fn nothing_matched() {
+    let (scratch, files) = packed("remove-nothing", &Context::default());
+    let result = remove(&scratch.join("archive.cats"), &["missing".to_string()], false, &Context::default());
+    assert!(matches!(result, Err(CatError::NoMatchingEntry(_))));
+    assert_eq!(read(&scratch.join("archive.cats"), &Context::default()), files);
+}
+
+#[test]
+fn remove_nested_patterns() {
+    let (scratch, mut files) = packed("remove-nested", &Context::default());
+    let patterns = ["large".to_string(), "large/a.bin".to_string()];
+    remove(&scratch.join("archive.cats"), &patterns, false, &Context::default()).unwrap();
+
+    files.retain(|path, _| !path.starts_with("large/"));
+    assert_eq!(read(&scratch.join("archive.cats"), &Context::default()), files);
+}
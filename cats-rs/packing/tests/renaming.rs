@@ -0,0 +1,64 @@
+mod common;
+
+use common::{encrypted, packed, read};
+use meta::error::CatError;
+use packing::editing::rename;
+
+#[test]
+fn rename_entries() {
+    let context = encrypted();
+    let (scratch, mut files) = packed("rename", &context);
+
+    rename(&scratch.join("archive.cats"), "a.txt", "moved/b.txt", &context).unwrap();
+    rename(&scratch.join("archive.cats"), "large", "huge", &context).unwrap();
+
+    let content = files.remove("a.txt").unwrap();
+    files.insert("moved/b.txt".to_string(), content);
+    for name in ["a.bin", "b.bin"] {
+        let content = files.remove(&format!("large/{name}")).unwrap();
+        files.insert(format!("huge/{name}"), content);
+    }
+    assert_eq!(read(&scratch.join("archive.cats"), &context), files);
+}
+
+#[test]
+fn invalid_names() {
+    let context = encrypted();
+    let (scratch, _) = packed("rename-invalid", &context);
+
+    let long = "n".repeat(300);
+    let result = rename(&scratch.join("archive.cats"), "empty", &long, &context);
+    assert!(matches!(result, Err(CatError::InvalidEntryName(_))));
+    let result = rename(&scratch.join("archive.cats"), "large", "large/inside", &context);
+    assert!(result.is_err());
+}
+
+#[test]
+fn move_into_directory() {
+    let context = encrypted();
+    let (scratch, mut files) = packed("rename-into", &context);
+
+    rename(&scratch.join("archive.cats"), "a.txt", "large", &context).unwrap();
+    rename(&scratch.join("archive.cats"), "data", "large/", &context).unwrap();
+
+    let content = files.remove("a.txt").unwrap();
+    files.insert("large/a.txt".to_string(), content);
+    let moved = files
+        .keys()
+        .filter(|path| path.starts_with("data/"))
+        .cloned()
+        .collect::<Vec<String>>();
+    for path in moved {
+        let content = files.remove(&path).unwrap();
+        files.insert(format!("large/{path}"), content);
+    }
+    assert_eq!(read(&scratch.join("archive.cats"), &context), files);
+
+    // Moving into a directory doesn't replace what is already there.
+    rename(&scratch.join("archive.cats"), "empty", "a.txt", &context).unwrap();
+    let result = rename(&scratch.join("archive.cats"), "a.txt", "large", &context);
+    assert!(matches!(result, Err(CatError::EntryExists(_))));
+    let content = files.remove("empty").unwrap();
+    files.insert("a.txt".to_string(), content);
+    assert_eq!(read(&scratch.join("archive.cats"), &context), files);
+}
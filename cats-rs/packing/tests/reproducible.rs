@@ -0,0 +1,50 @@
+mod common;
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use common::{create_tree, Scratch};
+use meta::metadata::Entry;
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+
+fn touch(path: &Path, time: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(time))
+        .unwrap();
+}
+
+#[test]
+fn independent_of_modification_times() {
+    let scratch = Scratch::new("reproducible");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("first.cats"), &Context::default()).unwrap();
+    touch(&scratch.join("source/a.txt"), UNIX_EPOCH + Duration::from_secs(1));
+    pack(&scratch.join("source"), &scratch.join("second.cats"), &Context::default()).unwrap();
+
+    assert_eq!(fs::read(scratch.join("first.cats")).unwrap(), fs::read(scratch.join("second.cats")).unwrap());
+}
+
+#[test]
+fn clamped_to_source_date_epoch() {
+    let scratch = Scratch::new("source-date-epoch");
+    create_tree(&scratch.join("source"));
+    touch(&scratch.join("source/empty"), UNIX_EPOCH + Duration::from_secs(100));
+    let context = Context {
+        times: true,
+        source_date_epoch: Some(1000),
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    let modified = |path: &str| match reader.entry(path) {
+        Some(Entry::File { attributes, .. }) => attributes.modified,
+        _ => panic!("Expected {path} to be a file"),
+    };
+    assert_eq!(modified("a.txt"), Some(1000));
+    assert_eq!(modified("empty"), Some(100));
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::fs;
+use common::{write, Scratch};
+use ed25519_dalek::SigningKey;
+use meta::metadata::Compression;
+use meta::Context;
+use packing::packing::pack;
+use packing::signing::sign;
+use unpacking::reader::CatReader;
+
+#[test]
+fn sign_archive_ending_like_a_signature() {
+    let scratch = Scratch::new("sign");
+    // Stored as is, so the data section ends in the signature magic.
+    let mut content = vec![b'x'; 300];
+    content.extend_from_slice(b"CSIG");
+    write(&scratch.join("source/a.txt"), &content);
+    let context = Context {
+        compression: Compression::None,
+        checksums: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+    let unsigned = fs::metadata(scratch.join("archive.cats")).unwrap().len();
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    sign(&scratch.join("archive.cats"), &key).unwrap();
+    let signed = fs::metadata(scratch.join("archive.cats")).unwrap().len();
+    assert!(signed > unsigned);
+
+    // Signing again replaces the signature instead of adding another one.
+    sign(&scratch.join("archive.cats"), &key).unwrap();
+    assert_eq!(fs::metadata(scratch.join("archive.cats")).unwrap().len(), signed);
+
+    let reader = CatReader::open_signed(&scratch.join("archive.cats"), &key.verifying_key()).unwrap();
+    assert_eq!(reader.read("a.txt").unwrap(), content);
+
+    let other = SigningKey::from_bytes(&[8; 32]);
+    assert!(CatReader::open_signed(&scratch.join("archive.cats"), &other.verifying_key()).is_err());
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::round_trip;
+use meta::metadata::Entry;
+use meta::{Context, Solid};
+
+#[test]
+fn solid_blocks() {
+    for (name, solid) in [("solid-directory", Solid::Directory), ("solid-extension", Solid::Extension)] {
+        let context = Context {
+            solid,
+            block_size: 4096,
+            ..Default::default()
+        };
+        let reader = round_trip(name, &context);
+        assert!(reader.header().blocks.len() > 1);
+        let Some(Entry::File { attributes, .. }) = reader.entry("data/1.json") else {
+            panic!("Expected data/1.json to be a file");
+        };
+        assert!(attributes.block.is_some());
+    }
+}
+
+#[test]
+fn cached_blocks() {
+    let context = Context {
+        solid: Solid::Directory,
+        ..Default::default()
+    };
+    let reader = round_trip("solid-cache", &context).with_block_cache(0);
+    assert_eq!(reader.read("data/1.json").unwrap(), reader.read("data/1.json").unwrap());
+}
@@ -0,0 +1,49 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use common::{write, Scratch};
+use meta::metadata::Entry;
+use meta::Context;
+use packing::packing::pack;
+use unpacking::reader::CatReader;
+use unpacking::unpacking::unpack;
+
+fn source(scratch: &Scratch) {
+    write(&scratch.join("source/dir/a.txt"), b"hello");
+    symlink("dir/a.txt", scratch.join("source/file-link")).unwrap();
+    symlink("dir", scratch.join("source/dir-link")).unwrap();
+}
+
+#[test]
+fn followed_by_default() {
+    let scratch = Scratch::new("symlinks-followed");
+    source(&scratch);
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert_eq!(reader.header().version, 1);
+    assert_eq!(reader.read("file-link").unwrap(), b"hello");
+    assert_eq!(reader.read("dir-link/a.txt").unwrap(), b"hello");
+}
+
+#[test]
+fn stored_when_asked_for() {
+    let scratch = Scratch::new("symlinks-stored");
+    source(&scratch);
+    let context = Context {
+        symlinks: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    assert!(matches!(reader.entry("file-link"), Some(Entry::Symlink { target, .. }) if target == "dir/a.txt"));
+    assert!(matches!(reader.entry("dir-link"), Some(Entry::Symlink { target, .. }) if target == "dir"));
+
+    unpack(&scratch.join("target"), &scratch.join("archive.cats"), &context).unwrap();
+    assert_eq!(fs::read_link(scratch.join("target/file-link")).unwrap().to_str(), Some("dir/a.txt"));
+    assert_eq!(fs::read(scratch.join("target/dir-link/a.txt")).unwrap(), b"hello");
+}
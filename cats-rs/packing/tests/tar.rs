@@ -0,0 +1,77 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use common::{create_tree, read_archive, read_tree, Scratch};
+use meta::Context;
+use packing::packing::pack;
+use packing::tarball::pack_tar;
+use tar::{Archive, Builder, EntryType, Header};
+use unpacking::reader::CatReader;
+use unpacking::tarball::unpack_tar;
+
+#[test]
+fn tar_round_trip() {
+    let scratch = Scratch::new("tar");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    let tar = unpack_tar(&reader, Vec::new(), &Context::default()).unwrap();
+    let mut files = BTreeMap::new();
+    for entry in Archive::new(tar.as_slice()).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        if entry.header().entry_type() == EntryType::Regular {
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            files.insert(path, content);
+        }
+    }
+    assert_eq!(files, read_tree(&scratch.join("source")));
+
+    pack_tar(tar.as_slice(), "tar", &scratch.join("converted.cats"), &Context::default()).unwrap();
+    let converted = CatReader::open(&scratch.join("converted.cats")).unwrap();
+    assert_eq!(read_archive(&converted), read_tree(&scratch.join("source")));
+}
+
+#[test]
+fn long_names() {
+    let scratch = Scratch::new("tar-long-names");
+    let long = format!("d/{}", "n".repeat(300));
+    let mut builder = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    header.set_entry_type(EntryType::Regular);
+    builder.append_data(&mut header, &long, b"hello".as_slice()).unwrap();
+    let tar = builder.into_inner().unwrap();
+
+    pack_tar(tar.as_slice(), "tar", &scratch.join("archive.cats"), &Context::default()).unwrap();
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    let files = read_archive(&reader);
+    assert_eq!(files.len(), 1);
+    let (path, content) = files.into_iter().next().unwrap();
+    assert_eq!(path.len(), 2 + 255);
+    assert!(path.starts_with("d/nnn"));
+    assert_eq!(content, b"hello");
+}
+
+#[test]
+fn truncated_entry() {
+    let scratch = Scratch::new("tar-truncated");
+    // The size claims far more than the stream holds, it must not be trusted up front.
+    let mut header = Header::new_gnu();
+    header.set_path("big.bin").unwrap();
+    header.set_size(0o77777777777);
+    header.set_mode(0o644);
+    header.set_entry_type(EntryType::Regular);
+    header.set_cksum();
+    let mut tar = header.as_bytes().to_vec();
+    tar.extend_from_slice(&[0; 512]);
+
+    let result = pack_tar(tar.as_slice(), "tar", &scratch.join("archive.cats"), &Context::default());
+    assert!(result.is_err());
+    let left = std::fs::read_dir(scratch.join("")).unwrap().count();
+    assert_eq!(left, 0);
+}
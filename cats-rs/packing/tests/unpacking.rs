@@ -0,0 +1,23 @@
+mod common;
+
+use common::{create_tree, read_tree, Scratch};
+use meta::Context;
+use packing::packing::pack;
+use unpacking::unpacking::unpack;
+
+#[test]
+fn unpack_directory() {
+    let scratch = Scratch::new("unpack");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+
+    for jobs in [1, 4] {
+        let context = Context {
+            jobs,
+            ..Default::default()
+        };
+        let target = scratch.join(&format!("target-{jobs}"));
+        unpack(&target, &scratch.join("archive.cats"), &context).unwrap();
+        assert_eq!(read_tree(&target), read_tree(&scratch.join("source")));
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use std::fs::{self, File};
+use std::time::{Duration, UNIX_EPOCH};
+use common::{create_tree, read_archive, read_tree, Scratch};
+use meta::error::CatError;
+use meta::metadata::Entry;
+use meta::Context;
+use packing::packing::pack;
+use packing::zipfile::pack_zip;
+use unpacking::reader::CatReader;
+use unpacking::zipfile::unpack_zip;
+
+#[test]
+fn zip_round_trip() {
+    let scratch = Scratch::new("zip");
+    create_tree(&scratch.join("source"));
+    // An odd number of seconds, which the DOS time in zip can't hold.
+    let modified = UNIX_EPOCH + Duration::from_secs(1704103203);
+    File::options()
+        .write(true)
+        .open(scratch.join("source/a.txt"))
+        .and_then(|file| file.set_modified(modified))
+        .unwrap();
+    let context = Context {
+        times: true,
+        ..Default::default()
+    };
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &context).unwrap();
+
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    let zip = unpack_zip(&reader, Vec::new(), &context).unwrap();
+    fs::write(scratch.join("archive.zip"), zip).unwrap();
+    pack_zip(&scratch.join("archive.zip"), &scratch.join("converted.cats"), &context).unwrap();
+
+    let converted = CatReader::open(&scratch.join("converted.cats")).unwrap();
+    assert_eq!(read_archive(&converted), read_tree(&scratch.join("source")));
+    let Some(Entry::File { attributes, .. }) = converted.entry("a.txt") else {
+        panic!("Expected a.txt to be a file");
+    };
+    assert_eq!(attributes.modified, Some(1704103203));
+}
+
+#[test]
+fn size_mismatch() {
+    let scratch = Scratch::new("zip-size");
+    create_tree(&scratch.join("source"));
+    pack(&scratch.join("source"), &scratch.join("archive.cats"), &Context::default()).unwrap();
+    let reader = CatReader::open(&scratch.join("archive.cats")).unwrap();
+    let mut zip = unpack_zip(&reader, Vec::new(), &Context::default()).unwrap();
+
+    // Claims one byte more for a.txt in the central directory than its content has.
+    let central = (0..zip.len() - 51)
+        .find(|&at| zip[at..].starts_with(b"PK\x01\x02") && &zip[at + 46..at + 51] == b"a.txt")
+        .unwrap();
+    zip[central + 24..central + 28].copy_from_slice(&6u32.to_le_bytes());
+    fs::write(scratch.join("archive.zip"), zip).unwrap();
+
+    let result = pack_zip(&scratch.join("archive.zip"), &scratch.join("converted.cats"), &Context::default());
+    assert!(matches!(result, Err(CatError::ErrorReadingFile { error, .. }) if error == "size mismatch"));
+}
@@ -1,28 +1,233 @@
 use std::io::Read;
 use meta::utils::EvalContext;
-use meta::utils::{read_string, read_u16, read_u32, read_u8, wrap_context};
+use meta::utils::{read_i64, read_long_string, read_string, read_u16, read_u32, read_u8, wrap_context};
 use meta::error::CatError;
-use meta::metadata::{Compression, Entry, Header};
+use meta::metadata::{
+    Attributes, Block, Chunk, Compression, Dictionary, Encryption, Entry, Header, ATTRIBUTE_BLOCK,
+    ATTRIBUTE_CHECKSUM, ATTRIBUTE_CHUNKS, ATTRIBUTE_DICTIONARY, ATTRIBUTE_MODE, ATTRIBUTE_MODIFIED, ENCRYPTION_ARGON2ID_XCHACHA20POLY1305, OPTIONAL_KEY,
+    SECTION_AUTHENTICATION, SECTION_BLOCKS, SECTION_CHUNKS, SECTION_DICTIONARIES, SECTION_ENCRYPTION,
+    SECTION_SEALED_HEADER,
+};
 
 pub trait CatDeserializable {
-    fn deserialize(reader: &mut impl Read, context: EvalContext) -> meta::error::Result<Self>
+    fn deserialize(reader: &mut impl Read, version: u8, context: EvalContext) -> meta::error::Result<Self>
     where
         Self: Sized;
 }
 
 impl CatDeserializable for Compression {
-    fn deserialize(reader: &mut impl Read, context: EvalContext) -> meta::error::Result<Compression> {
-        let value = wrap_context(read_u8(reader), context, CatError::ErrorReadingMetadata)?;
+    fn deserialize(reader: &mut impl Read, version: u8, context: EvalContext) -> meta::error::Result<Compression> {
+        let value = wrap_context(read_u8(reader), context.clone(), CatError::ErrorReadingMetadata)?;
         match value {
+            0xFD if version >= 2 => Ok(Compression::Zstd),
             0xFE => Ok(Compression::Gzip),
             0xFF => Ok(Compression::None),
-            _ => panic!("Invalid compression! {}", value),
+            _ => CatError::InvalidMetadata(context).into(),
         }
     }
 }
 
+impl CatDeserializable for Attributes {
+    fn deserialize(reader: &mut impl Read, _: u8, context: EvalContext) -> meta::error::Result<Attributes> {
+        let amount = wrap_context(
+            read_u8(reader),
+            context.push("attribute count".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+        let mut attributes = Attributes::default();
+        for _ in 0..amount {
+            let (key, value) = read_block(reader, context.push("attribute".to_string()))?;
+            let context = context.push(format!("attribute {key}"));
+            match key {
+                ATTRIBUTE_DICTIONARY => {
+                    attributes.dictionary = Some(wrap_context(
+                        read_u16(&mut value.as_slice()),
+                        context,
+                        CatError::ErrorReadingMetadata,
+                    )?)
+                }
+                ATTRIBUTE_BLOCK => {
+                    attributes.block = Some(wrap_context(
+                        read_u32(&mut value.as_slice()),
+                        context,
+                        CatError::ErrorReadingMetadata,
+                    )?)
+                }
+                ATTRIBUTE_CHUNKS => {
+                    if value.len() % 4 != 0 {
+                        return CatError::InvalidMetadata(context).into();
+                    }
+                    attributes.chunks = Some(
+                        value
+                            .chunks_exact(4)
+                            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                            .collect(),
+                    )
+                }
+                ATTRIBUTE_CHECKSUM => {
+                    attributes.checksum = Some(wrap_context(
+                        read_u32(&mut value.as_slice()),
+                        context,
+                        CatError::ErrorReadingMetadata,
+                    )?)
+                }
+                ATTRIBUTE_MODE => {
+                    attributes.mode = Some(wrap_context(
+                        read_u16(&mut value.as_slice()),
+                        context,
+                        CatError::ErrorReadingMetadata,
+                    )?)
+                }
+                ATTRIBUTE_MODIFIED => {
+                    attributes.modified = Some(wrap_context(
+                        read_i64(&mut value.as_slice()),
+                        context,
+                        CatError::ErrorReadingMetadata,
+                    )?)
+                }
+                _ if key >= OPTIONAL_KEY => {}
+                _ => return CatError::UnknownAttribute(context, key).into(),
+            }
+        }
+
+        Ok(attributes)
+    }
+}
+
+impl CatDeserializable for Vec<Dictionary> {
+    fn deserialize(reader: &mut impl Read, _: u8, context: EvalContext) -> meta::error::Result<Vec<Dictionary>> {
+        let amount = wrap_context(
+            read_u16(reader),
+            context.push("dictionary count".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+        let mut dictionaries = Vec::<Dictionary>::new();
+        for i in 0..amount {
+            let context = context.push(i.to_string());
+            dictionaries.push(Dictionary {
+                offset: wrap_context(
+                    read_u32(reader),
+                    context.push("offset".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+                size: wrap_context(
+                    read_u32(reader),
+                    context.push("size".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+            })
+        }
+
+        Ok(dictionaries)
+    }
+}
+
+impl CatDeserializable for Vec<Block> {
+    fn deserialize(reader: &mut impl Read, version: u8, context: EvalContext) -> meta::error::Result<Vec<Block>> {
+        let amount = wrap_context(
+            read_u32(reader),
+            context.push("block count".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+        let mut blocks = Vec::<Block>::new();
+        for i in 0..amount {
+            let context = context.push(i.to_string());
+            blocks.push(Block {
+                offset: wrap_context(
+                    read_u32(reader),
+                    context.push("offset".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+                size: wrap_context(
+                    read_u32(reader),
+                    context.push("size".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+                compression: Compression::deserialize(
+                    reader,
+                    version,
+                    context.push("compression".to_string()),
+                )?,
+            })
+        }
+
+        Ok(blocks)
+    }
+}
+
+impl CatDeserializable for Vec<Chunk> {
+    fn deserialize(reader: &mut impl Read, version: u8, context: EvalContext) -> meta::error::Result<Vec<Chunk>> {
+        let amount = wrap_context(
+            read_u32(reader),
+            context.push("chunk count".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+        let mut chunks = Vec::<Chunk>::new();
+        for i in 0..amount {
+            let context = context.push(i.to_string());
+            chunks.push(Chunk {
+                offset: wrap_context(
+                    read_u32(reader),
+                    context.push("offset".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+                size: wrap_context(
+                    read_u32(reader),
+                    context.push("size".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?,
+                compression: Compression::deserialize(
+                    reader,
+                    version,
+                    context.push("compression".to_string()),
+                )?,
+            })
+        }
+
+        Ok(chunks)
+    }
+}
+
+impl CatDeserializable for Encryption {
+    fn deserialize(reader: &mut impl Read, _: u8, context: EvalContext) -> meta::error::Result<Encryption> {
+        let algorithm = wrap_context(
+            read_u8(reader),
+            context.push("algorithm".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+        if algorithm != ENCRYPTION_ARGON2ID_XCHACHA20POLY1305 {
+            return CatError::InvalidMetadata(context.push("algorithm".to_string())).into();
+        }
+        let mut salt = [0u8; 16];
+        wrap_context(
+            reader.read_exact(&mut salt),
+            context.push("salt".to_string()),
+            CatError::ErrorReadingMetadata,
+        )?;
+
+        Ok(Encryption {
+            salt,
+            memory: wrap_context(
+                read_u32(reader),
+                context.push("memory".to_string()),
+                CatError::ErrorReadingMetadata,
+            )?,
+            iterations: wrap_context(
+                read_u32(reader),
+                context.push("iterations".to_string()),
+                CatError::ErrorReadingMetadata,
+            )?,
+            parallelism: wrap_context(
+                read_u8(reader),
+                context.push("parallelism".to_string()),
+                CatError::ErrorReadingMetadata,
+            )?,
+        })
+    }
+}
+
 impl CatDeserializable for Entry {
-    fn deserialize(reader: &mut impl Read, context: EvalContext) -> meta::error::Result<Entry> {
+    fn deserialize(reader: &mut impl Read, version: u8, context: EvalContext) -> meta::error::Result<Entry> {
         let data = wrap_context(
             read_u8(reader),
             context.push("entry type".to_string()),
@@ -35,6 +240,11 @@ impl CatDeserializable for Entry {
                     context.push("file name".to_string()),
                     CatError::ErrorReadingMetadata,
                 )?;
+                let attributes = if version >= 2 {
+                    Attributes::deserialize(reader, version, context.push(name.to_string()))?
+                } else {
+                    Attributes::default()
+                };
                 let offset = wrap_context(
                     read_u32(reader),
                     context.push("offset".to_string()),
@@ -47,6 +257,7 @@ impl CatDeserializable for Entry {
                 )?;
                 let compression = Compression::deserialize(
                     reader,
+                    version,
                     context
                         .push(name.to_string())
                         .push("compression".to_string()),
@@ -57,6 +268,7 @@ impl CatDeserializable for Entry {
                     offset,
                     size,
                     compression,
+                    attributes,
                 })
             }
             1 => {
@@ -65,6 +277,11 @@ impl CatDeserializable for Entry {
                     context.push("directory name".to_string()),
                     CatError::ErrorReadingMetadata,
                 )?;
+                let attributes = if version >= 2 {
+                    Attributes::deserialize(reader, version, context.push(name.to_string()))?
+                } else {
+                    Attributes::default()
+                };
                 let amount = wrap_context(
                     read_u16(reader),
                     context.push("directory name".to_string()),
@@ -72,10 +289,33 @@ impl CatDeserializable for Entry {
                 )?;
                 let mut entries = Vec::<Entry>::new();
                 for _ in 0..amount {
-                    entries.push(Entry::deserialize(reader, context.push(name.to_string()))?)
+                    entries.push(Entry::deserialize(reader, version, context.push(name.to_string()))?)
                 }
 
-                Ok(Entry::Directory { name, entries })
+                Ok(Entry::Directory {
+                    name,
+                    entries,
+                    attributes,
+                })
+            }
+            2 if version >= 2 => {
+                let name = wrap_context(
+                    read_string(reader),
+                    context.push("link name".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?;
+                let attributes = Attributes::deserialize(reader, version, context.push(name.to_string()))?;
+                let target = wrap_context(
+                    read_long_string(reader),
+                    context.push(name.to_string()).push("link target".to_string()),
+                    CatError::ErrorReadingMetadata,
+                )?;
+
+                Ok(Entry::Symlink {
+                    name,
+                    target,
+                    attributes,
+                })
             }
             _ => CatError::InvalidEntryType(context, data).into(),
         }
@@ -83,12 +323,52 @@ impl CatDeserializable for Entry {
 }
 
 impl CatDeserializable for Header {
-    fn deserialize(reader: &mut impl Read, context: EvalContext) -> meta::error::Result<Header> {
+    fn deserialize(reader: &mut impl Read, _: u8, context: EvalContext) -> meta::error::Result<Header> {
         let version = wrap_context(
             read_u8(reader),
             context.push("version".to_string()),
             CatError::ErrorReadingMetadata,
         )?;
+        if version == 0 || version > 2 {
+            return CatError::UnknownVersion.into();
+        }
+
+        let mut dictionaries = Vec::<Dictionary>::new();
+        let mut blocks = Vec::<Block>::new();
+        let mut chunks = Vec::<Chunk>::new();
+        let mut encryption = None;
+        let mut sealed = None;
+        let mut authentication = None;
+        if version >= 2 {
+            let amount = wrap_context(
+                read_u8(reader),
+                context.push("section count".to_string()),
+                CatError::ErrorReadingMetadata,
+            )?;
+            for _ in 0..amount {
+                let (key, value) = read_block(reader, context.push("section".to_string()))?;
+                let context = context.push(format!("section {key}"));
+                match key {
+                    SECTION_DICTIONARIES => {
+                        dictionaries = Vec::<Dictionary>::deserialize(&mut value.as_slice(), version, context)?
+                    }
+                    SECTION_BLOCKS => {
+                        blocks = Vec::<Block>::deserialize(&mut value.as_slice(), version, context)?
+                    }
+                    SECTION_CHUNKS => {
+                        chunks = Vec::<Chunk>::deserialize(&mut value.as_slice(), version, context)?
+                    }
+                    SECTION_ENCRYPTION => {
+                        encryption = Some(Encryption::deserialize(&mut value.as_slice(), version, context)?)
+                    }
+                    SECTION_SEALED_HEADER => sealed = Some(value),
+                    SECTION_AUTHENTICATION => authentication = Some(value),
+                    _ if key >= OPTIONAL_KEY => {}
+                    _ => return CatError::UnknownSection(context, key).into(),
+                }
+            }
+        }
+
         let amount = wrap_context(
             read_u16(reader),
             context.push("entries length".to_string()),
@@ -96,9 +376,35 @@ impl CatDeserializable for Header {
         )?;
         let mut entries = Vec::<Entry>::new();
         for i in 0..amount {
-            entries.push(Entry::deserialize(reader, context.push(i.to_string()))?.clone())
+            entries.push(Entry::deserialize(reader, version, context.push(i.to_string()))?.clone())
         }
 
-        Ok(Header { version, entries })
+        Ok(Header {
+            version,
+            dictionaries,
+            blocks,
+            chunks,
+            encryption,
+            sealed,
+            authentication,
+            entries,
+        })
+    }
+}
+
+/// Reads a key followed by the length prefixed value, used for both sections and attributes.
+fn read_block(reader: &mut impl Read, context: EvalContext) -> meta::error::Result<(u8, Vec<u8>)> {
+    let key = wrap_context(read_u8(reader), context.clone(), CatError::ErrorReadingMetadata)?;
+    let length = wrap_context(read_u32(reader), context.clone(), CatError::ErrorReadingMetadata)?;
+    let mut value = Vec::<u8>::new();
+    wrap_context(
+        reader.take(length as u64).read_to_end(&mut value),
+        context.clone(),
+        CatError::ErrorReadingMetadata,
+    )?;
+    if value.len() != length as usize {
+        return CatError::InvalidMetadata(context).into();
     }
+
+    Ok((key, value))
 }
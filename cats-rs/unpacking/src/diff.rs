@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use meta::metadata::{Compression, Entry};
+use meta::utils::EvalContext;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use xxhash_rust::xxh3::xxh3_64;
+use crate::reader::CatReader;
+
+/// A single difference between two archives, paths are `/` separated and relative to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Added { path: String, kind: Kind },
+    Removed { path: String, kind: Kind },
+    /// The content of a file or the target of a symbolic link changed.
+    Modified { path: String, kind: Kind },
+    /// A file disappeared at one path and the same content showed up at another.
+    Moved { from: String, to: String },
+    /// A file kept its content but is stored with another codec.
+    Compression {
+        path: String,
+        before: Compression,
+        after: Compression,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink,
+}
+
+impl Difference {
+    pub fn path(&self) -> &str {
+        match self {
+            Difference::Added { path, .. }
+            | Difference::Removed { path, .. }
+            | Difference::Modified { path, .. }
+            | Difference::Compression { path, .. } => path,
+            Difference::Moved { to, .. } => to,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Difference::Added { path, kind } => json!({ "change": "added", "path": path, "kind": kind.to_string() }),
+            Difference::Removed { path, kind } => {
+                json!({ "change": "removed", "path": path, "kind": kind.to_string() })
+            }
+            Difference::Modified { path, kind } => {
+                json!({ "change": "modified", "path": path, "kind": kind.to_string() })
+            }
+            Difference::Moved { from, to } => json!({ "change": "moved", "from": from, "to": to }),
+            Difference::Compression { path, before, after } => json!({
+                "change": "compression",
+                "path": path,
+                "before": before.to_string(),
+                "after": after.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Added { path, kind } => write!(f, "A {path}{}", kind.suffix()),
+            Difference::Removed { path, kind } => write!(f, "D {path}{}", kind.suffix()),
+            Difference::Modified { path, kind } => write!(f, "M {path}{}", kind.suffix()),
+            Difference::Moved { from, to } => write!(f, "R {from} -> {to}"),
+            Difference::Compression { path, before, after } => write!(f, "C {path} ({before} -> {after})"),
+        }
+    }
+}
+
+impl Kind {
+    fn suffix(&self) -> &'static str {
+        match self {
+            Kind::File => "",
+            Kind::Directory => "/",
+            Kind::Symlink => "@",
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::File => f.write_str("file"),
+            Kind::Directory => f.write_str("directory"),
+            Kind::Symlink => f.write_str("symlink"),
+        }
+    }
+}
+
+/// What is stored at a path, files are compared by the hash and size of their content.
+#[derive(PartialEq, Eq)]
+enum Item {
+    File {
+        hash: u64,
+        size: usize,
+        compression: Compression,
+    },
+    Directory,
+    Symlink(String),
+}
+
+impl Item {
+    fn kind(&self) -> Kind {
+        match self {
+            Item::File { .. } => Kind::File,
+            Item::Directory => Kind::Directory,
+            Item::Symlink(_) => Kind::Symlink,
+        }
+    }
+}
+
+/// Compares two archives, sorted by path. A removed file whose content was added at another path
+/// is reported as moved.
+pub fn diff(before: &CatReader, after: &CatReader) -> meta::error::Result<Vec<Difference>> {
+    let before = items(before)?;
+    let after = items(after)?;
+
+    let mut differences = Vec::<Difference>::new();
+    let mut removed = Vec::<(&String, &Item)>::new();
+    let mut added = Vec::<(&String, &Item)>::new();
+    for (path, item) in &before {
+        match after.get(path) {
+            None => removed.push((path, item)),
+            Some(other) if other.kind() != item.kind() => {
+                removed.push((path, item));
+                added.push((path, other));
+            }
+            Some(other) => match (item, other) {
+                (
+                    Item::File {
+                        hash,
+                        size,
+                        compression,
+                    },
+                    Item::File {
+                        hash: other_hash,
+                        size: other_size,
+                        compression: other_compression,
+                    },
+                ) => {
+                    if hash != other_hash || size != other_size {
+                        differences.push(Difference::Modified {
+                            path: path.clone(),
+                            kind: Kind::File,
+                        });
+                    } else if compression != other_compression {
+                        differences.push(Difference::Compression {
+                            path: path.clone(),
+                            before: compression.clone(),
+                            after: other_compression.clone(),
+                        });
+                    }
+                }
+                _ if item != other => differences.push(Difference::Modified {
+                    path: path.clone(),
+                    kind: item.kind(),
+                }),
+                _ => {}
+            },
+        }
+    }
+    added.extend(after.iter().filter(|(path, _)| !before.contains_key(*path)));
+
+    // Every added file can only be the destination of one move.
+    let mut destinations = HashMap::<(u64, usize), Vec<&String>>::new();
+    for (path, item) in added.iter().rev() {
+        if let Item::File { hash, size, .. } = item {
+            destinations.entry((*hash, *size)).or_default().push(path);
+        }
+    }
+    let mut moved = HashSet::<&String>::new();
+    for (path, item) in removed {
+        let destination = match item {
+            Item::File { hash, size, .. } => destinations.get_mut(&(*hash, *size)).and_then(Vec::pop),
+            _ => None,
+        };
+        match destination {
+            Some(destination) => {
+                moved.insert(destination);
+                differences.push(Difference::Moved {
+                    from: path.clone(),
+                    to: destination.clone(),
+                });
+            }
+            None => differences.push(Difference::Removed {
+                path: path.clone(),
+                kind: item.kind(),
+            }),
+        }
+    }
+    for (path, item) in added {
+        if !moved.contains(&path) || !matches!(item, Item::File { .. }) {
+            differences.push(Difference::Added {
+                path: path.clone(),
+                kind: item.kind(),
+            });
+        }
+    }
+
+    differences.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(differences)
+}
+
+/// Renders the differences as a JSON array.
+pub fn to_json(differences: &[Difference]) -> String {
+    Value::Array(differences.iter().map(Difference::to_json).collect()).to_string()
+}
+
+fn items(reader: &CatReader) -> meta::error::Result<BTreeMap<String, Item>> {
+    let mut entries = Vec::<(String, &Entry)>::new();
+    collect_entries(&reader.header().entries, "", &mut entries);
+
+    entries
+        .into_par_iter()
+        .map(|(path, entry)| {
+            let item = match entry {
+                Entry::Directory { .. } => Item::Directory,
+                Entry::Symlink { target, .. } => Item::Symlink(target.clone()),
+                Entry::File { .. } => {
+                    let content = reader.read_entry(entry, &EvalContext::new(path.clone()))?;
+                    Item::File {
+                        hash: xxh3_64(&content),
+                        size: content.len(),
+                        compression: stored_compression(reader, entry),
+                    }
+                }
+            };
+            Ok((path, item))
+        })
+        .collect()
+}
+
+fn collect_entries<'a>(entries: &'a [Entry], parent: &str, collected: &mut Vec<(String, &'a Entry)>) {
+    for entry in entries {
+        let path = match parent {
+            "" => entry.name().to_string(),
+            parent => format!("{parent}/{}", entry.name()),
+        };
+        if let Entry::Directory { entries, .. } = entry {
+            collect_entries(entries, &path, collected);
+        }
+        collected.push((path, entry));
+    }
+}
+
+/// The codec the content of a file is actually stored with, which is the one of its solid block
+/// or chunks if it lives in those.
+fn stored_compression(reader: &CatReader, entry: &Entry) -> Compression {
+    let Entry::File {
+        compression,
+        attributes,
+        ..
+    } = entry
+    else {
+        return Compression::None;
+    };
+    let header = reader.header();
+    if let Some(chunk) = attributes.chunks.as_ref().and_then(|chunks| chunks.first())
+        && let Some(chunk) = header.chunks.get(*chunk as usize)
+    {
+        return chunk.compression.clone();
+    }
+    if let Some(block) = attributes.block
+        && let Some(block) = header.blocks.get(block as usize)
+    {
+        return block.compression.clone();
+    }
+    compression.clone()
+}
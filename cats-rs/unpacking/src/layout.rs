@@ -0,0 +1,554 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use meta::crypto::{Cipher, Piece};
+use meta::metadata::{
+    Entry, Header, ATTRIBUTE_BLOCK, ATTRIBUTE_CHECKSUM, ATTRIBUTE_CHUNKS, ATTRIBUTE_DICTIONARY,
+    ATTRIBUTE_MODE, ATTRIBUTE_MODIFIED, ENCRYPTION_ARGON2ID_XCHACHA20POLY1305, MAGIC_NUMBER, SECTION_AUTHENTICATION,
+    SECTION_BLOCKS, SECTION_CHUNKS, SECTION_DICTIONARIES, SECTION_ENCRYPTION, SECTION_SEALED_HEADER, SIGNATURE_LENGTH,
+};
+use meta::utils::EvalContext;
+use meta::Context;
+use crate::reader::{split_header, CatReader};
+use crate::signature::is_signed;
+
+/// How many bytes of a field are shown, longer ones are cut off.
+const SHOWN_BYTES: usize = 8;
+
+/// Where every field of the header is stored and what the data section holds.
+pub struct Layout {
+    pub header: Vec<Field>,
+    /// The decrypted remainder of a sealed header, offsets are relative to its start.
+    pub sealed: Vec<Field>,
+    /// Why the header couldn't be walked any further, nothing after that point is listed.
+    pub error: Option<String>,
+    /// Where the data section starts in the archive.
+    pub data_offset: usize,
+    pub data: Vec<Region>,
+}
+
+/// A single field of the header as stored.
+pub struct Field {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub name: String,
+    pub value: String,
+    /// How deep inside sections, entries and attributes the field is.
+    pub depth: usize,
+}
+
+/// A piece of the data section, cut wherever something referenced by the header starts or ends.
+/// Offsets are relative to the start of the data section.
+pub struct Region {
+    pub offset: usize,
+    pub size: usize,
+    /// What is stored here, empty if nothing references it.
+    pub contents: Vec<String>,
+}
+
+/// The overall shape of an archive. What the header lists is only known if it isn't sealed or the
+/// password was given.
+pub struct Summary {
+    pub version: u8,
+    pub header_size: usize,
+    pub data_size: usize,
+    pub encrypted: bool,
+    pub sealed: bool,
+    pub signed: bool,
+    pub header: Option<Header>,
+}
+
+pub fn summary(content: &[u8], context: &Context) -> meta::error::Result<Summary> {
+    let (header, data) = split_header(content)?;
+    let known = match (&header.sealed, &context.password) {
+        (Some(_), Some(_)) => Some(CatReader::from_bytes_with(content.to_vec(), context)?.header().clone()),
+        (Some(_), None) => None,
+        (None, _) => Some(header.clone()),
+    };
+
+    Ok(Summary {
+        version: header.version,
+        header_size: content.len() - data.len(),
+        data_size: data.len(),
+        encrypted: header.encryption.is_some(),
+        sealed: header.sealed.is_some(),
+        signed: is_signed(content),
+        header: known,
+    })
+}
+
+/// Walks the header of the archive field by field, recording where each one is stored, and maps
+/// the data section. A sealed header is only walked if the context has the password. A malformed
+/// header is walked up to the first field that doesn't make sense.
+pub fn layout(content: &[u8], context: &Context) -> meta::error::Result<Layout> {
+    let mut walker = Walker::new(content);
+    let walked = walker.header(true);
+    let mut layout = Layout {
+        header: walker.fields,
+        sealed: Vec::new(),
+        error: walked.err(),
+        data_offset: walker.position,
+        data: Vec::new(),
+    };
+    if layout.error.is_some() {
+        return Ok(layout);
+    }
+
+    let (header, data) = match split_header(content) {
+        Ok(split) => split,
+        Err(err) => {
+            layout.error = Some(err.to_string());
+            return Ok(layout);
+        }
+    };
+    let mut known = header.sealed.is_none().then(|| header.clone());
+    if let (Some(sealed), Some(encryption), Some(password)) = (&header.sealed, &header.encryption, &context.password) {
+        let eval_context = EvalContext::new("header".to_string());
+        let inner = Cipher::new(password, encryption, &eval_context)?.open(
+            sealed,
+            &Piece::SealedHeader.at(0),
+            &eval_context,
+        )?;
+        let mut walker = Walker::new(&inner);
+        let walked = walker.header(false);
+        layout.sealed = walker.fields;
+        if let Err(err) = walked {
+            layout.error = Some(err);
+            return Ok(layout);
+        }
+        known = Some(CatReader::from_bytes_with(content.to_vec(), context)?.header().clone());
+    }
+
+    let signed = is_signed(content);
+    if signed && data.len() < SIGNATURE_LENGTH {
+        layout.error = Some("the signature reaches into the header".to_string());
+        return Ok(layout);
+    }
+    layout.data = data_regions(known.as_ref(), data, signed);
+    Ok(layout)
+}
+
+/// Cuts the data section wherever a dictionary, block, chunk, file or the signature starts or
+/// ends. Without a header only the signature is known, if the archive is signed.
+pub fn data_regions(header: Option<&Header>, data: &[u8], signed: bool) -> Vec<Region> {
+    let mut ranges = Vec::<(usize, usize, String)>::new();
+    if let Some(header) = header {
+        for (i, dictionary) in header.dictionaries.iter().enumerate() {
+            ranges.push(range(dictionary.offset, dictionary.size, format!("dictionary {i}")));
+        }
+        for (i, block) in header.blocks.iter().enumerate() {
+            ranges.push(range(block.offset, block.size, format!("block {i}")));
+        }
+        for (i, chunk) in header.chunks.iter().enumerate() {
+            ranges.push(range(chunk.offset, chunk.size, format!("chunk {i}")));
+        }
+        collect_files(&header.entries, "", &mut ranges);
+    }
+    // A signature longer than the data section reaches into the header, it isn't mapped then.
+    if let Some(start) = data.len().checked_sub(SIGNATURE_LENGTH).filter(|_| signed) {
+        ranges.push((start, data.len(), "signature".to_string()));
+    }
+
+    let mut cuts = BTreeSet::from([0, data.len()]);
+    for (start, end, _) in &ranges {
+        cuts.insert((*start).min(data.len()));
+        cuts.insert((*end).min(data.len()));
+    }
+    let cuts = cuts.into_iter().collect::<Vec<usize>>();
+    cuts.windows(2)
+        .map(|window| {
+            let (start, end) = (window[0], window[1]);
+            Region {
+                offset: start,
+                size: end - start,
+                contents: ranges
+                    .iter()
+                    .filter(|(from, to, _)| *from <= start && *to >= end && from != to)
+                    .map(|(from, to, name)| match *from == start && *to == end {
+                        true => name.clone(),
+                        false => format!("part of {name}"),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Counts the files, directories and symbolic links below the entries.
+fn count_entries(entries: &[Entry], counts: &mut [usize; 3]) {
+    for entry in entries {
+        match entry {
+            Entry::File { .. } => counts[0] += 1,
+            Entry::Directory { entries, .. } => {
+                counts[1] += 1;
+                count_entries(entries, counts);
+            }
+            Entry::Symlink { .. } => counts[2] += 1,
+        }
+    }
+}
+
+fn range(offset: u32, size: u32, name: String) -> (usize, usize, String) {
+    (offset as usize, offset as usize + size as usize, name)
+}
+
+/// Collects the ranges of the data section holding files, files inside solid blocks or made of
+/// chunks point elsewhere.
+fn collect_files(entries: &[Entry], parent: &str, ranges: &mut Vec<(usize, usize, String)>) {
+    for entry in entries {
+        let path = match parent {
+            "" => entry.name().to_string(),
+            parent => format!("{parent}/{}", entry.name()),
+        };
+        match entry {
+            Entry::Directory { entries, .. } => collect_files(entries, &path, ranges),
+            Entry::File {
+                offset,
+                size,
+                attributes,
+                ..
+            } if attributes.block.is_none() && attributes.chunks.is_none() => {
+                ranges.push(range(*offset, *size, format!("file {path}")))
+            }
+            Entry::File { .. } | Entry::Symlink { .. } => {}
+        }
+    }
+}
+
+/// Reads fields one after another, mirroring the deserializer but keeping every field it reads.
+struct Walker<'a> {
+    content: &'a [u8],
+    position: usize,
+    depth: usize,
+    fields: Vec<Field>,
+}
+
+type Walked<T> = Result<T, String>;
+
+impl<'a> Walker<'a> {
+    fn new(content: &'a [u8]) -> Walker<'a> {
+        Walker {
+            content,
+            position: 0,
+            depth: 0,
+            fields: Vec::new(),
+        }
+    }
+
+    fn field(&mut self, length: usize, name: &str, value: impl FnOnce(&[u8]) -> String) -> Walked<&'a [u8]> {
+        let left = self.content.len() - self.position;
+        if length > left {
+            return Err(format!(
+                "{name} at {:08x} needs {length} bytes, only {left} are left",
+                self.position
+            ));
+        }
+
+        let bytes = &self.content[self.position..self.position + length];
+        self.fields.push(Field {
+            offset: self.position,
+            bytes: bytes.to_vec(),
+            name: name.to_string(),
+            value: value(bytes),
+            depth: self.depth,
+        });
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self, name: &str) -> Walked<u8> {
+        Ok(self.field(1, name, |bytes| bytes[0].to_string())?[0])
+    }
+
+    fn u16(&mut self, name: &str) -> Walked<u16> {
+        let bytes = self.field(2, name, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]).to_string())?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self, name: &str) -> Walked<u32> {
+        let bytes = self.field(4, name, |bytes| be_u32(bytes).to_string())?;
+        Ok(be_u32(bytes))
+    }
+
+    fn string(&mut self, name: &str, long: bool) -> Walked<()> {
+        let length = match long {
+            true => self.u16(&format!("{name} length"))? as usize,
+            false => self.u8(&format!("{name} length"))? as usize,
+        };
+        self.field(length, name, |bytes| format!("{:?}", String::from_utf8_lossy(bytes)))?;
+        Ok(())
+    }
+
+    fn compression(&mut self) -> Walked<()> {
+        self.field(1, "compression", |bytes| match bytes[0] {
+            0xFD => "zstd".to_string(),
+            0xFE => "gzip".to_string(),
+            0xFF => "none".to_string(),
+            other => format!("unknown ({other:#04x})"),
+        })?;
+        Ok(())
+    }
+
+    fn nested(&mut self, walk: impl FnOnce(&mut Self) -> Walked<()>) -> Walked<()> {
+        self.depth += 1;
+        let walked = walk(self);
+        self.depth -= 1;
+        walked
+    }
+
+    fn header(&mut self, magic: bool) -> Walked<()> {
+        if magic {
+            self.field(MAGIC_NUMBER.len(), "magic", |bytes| match bytes == MAGIC_NUMBER {
+                true => "CATS".to_string(),
+                false => "not a CATS archive".to_string(),
+            })?;
+            if self.content[..MAGIC_NUMBER.len()] != MAGIC_NUMBER {
+                return Err("the archive doesn't start with the magic number".to_string());
+            }
+        }
+
+        let version = self.u8("version")?;
+        if version >= 2 {
+            let count = self.u8("section count")?;
+            for _ in 0..count {
+                self.section()?;
+            }
+        }
+        let count = self.u16("entry count")?;
+        for _ in 0..count {
+            self.entry(version)?;
+        }
+        Ok(())
+    }
+
+    fn section(&mut self) -> Walked<()> {
+        let key = self.field(1, "section", |bytes| {
+            match bytes[0] {
+                SECTION_DICTIONARIES => "dictionaries",
+                SECTION_BLOCKS => "blocks",
+                SECTION_CHUNKS => "chunks",
+                SECTION_ENCRYPTION => "encryption",
+                SECTION_SEALED_HEADER => "sealed header",
+                SECTION_AUTHENTICATION => "authentication",
+                _ => "unknown",
+            }
+            .to_string()
+        })?[0];
+        let length = self.u32("section length")? as usize;
+        let end = self.position + length;
+
+        self.nested(|walker| match key {
+            SECTION_DICTIONARIES => {
+                let count = walker.u16("dictionary count")?;
+                for i in 0..count {
+                    walker.u32(&format!("dictionary {i} offset"))?;
+                    walker.u32(&format!("dictionary {i} size"))?;
+                }
+                Ok(())
+            }
+            SECTION_BLOCKS | SECTION_CHUNKS => {
+                let name = match key {
+                    SECTION_BLOCKS => "block",
+                    _ => "chunk",
+                };
+                let count = walker.u32(&format!("{name} count"))?;
+                for i in 0..count {
+                    walker.u32(&format!("{name} {i} offset"))?;
+                    walker.u32(&format!("{name} {i} size"))?;
+                    walker.compression()?;
+                }
+                Ok(())
+            }
+            SECTION_ENCRYPTION => {
+                walker.field(1, "algorithm", |bytes| match bytes[0] {
+                    ENCRYPTION_ARGON2ID_XCHACHA20POLY1305 => "Argon2id, XChaCha20-Poly1305".to_string(),
+                    other => format!("unknown ({other:#04x})"),
+                })?;
+                walker.field(16, "salt", hex)?;
+                walker.u32("memory")?;
+                walker.u32("iterations")?;
+                walker.u8("parallelism")?;
+                Ok(())
+            }
+            SECTION_AUTHENTICATION => {
+                walker.field(length, "tag", hex)?;
+                Ok(())
+            }
+            _ => {
+                walker.field(length, "content", |bytes| format!("{} bytes", bytes.len()))?;
+                Ok(())
+            }
+        })?;
+
+        match self.position == end {
+            true => Ok(()),
+            false => Err(format!("the section ends at {:08x}, not at {end:08x}", self.position)),
+        }
+    }
+
+    fn entry(&mut self, version: u8) -> Walked<()> {
+        let kind = self.field(1, "entry", |bytes| {
+            match bytes[0] {
+                0 => "file",
+                1 => "directory",
+                2 => "symlink",
+                _ => "unknown",
+            }
+            .to_string()
+        })?[0];
+
+        self.nested(|walker| {
+            walker.string("name", false)?;
+            if version >= 2 {
+                let count = walker.u8("attribute count")?;
+                for _ in 0..count {
+                    walker.attribute()?;
+                }
+            }
+            match kind {
+                0 => {
+                    walker.u32("offset")?;
+                    walker.u32("size")?;
+                    walker.compression()
+                }
+                1 => {
+                    let count = walker.u16("entry count")?;
+                    for _ in 0..count {
+                        walker.entry(version)?;
+                    }
+                    Ok(())
+                }
+                2 => walker.string("target", true),
+                kind => Err(format!("entry type {kind} is unknown")),
+            }
+        })
+    }
+
+    fn attribute(&mut self) -> Walked<()> {
+        let key = self.field(1, "attribute", |bytes| {
+            match bytes[0] {
+                ATTRIBUTE_DICTIONARY => "dictionary",
+                ATTRIBUTE_BLOCK => "block",
+                ATTRIBUTE_CHUNKS => "chunks",
+                ATTRIBUTE_CHECKSUM => "checksum",
+                ATTRIBUTE_MODE => "mode",
+                ATTRIBUTE_MODIFIED => "modified",
+                _ => "unknown",
+            }
+            .to_string()
+        })?[0];
+        let length = self.u32("attribute length")? as usize;
+
+        self.nested(|walker| {
+            walker.field(length, "value", |bytes| match (key, bytes.len()) {
+                (ATTRIBUTE_DICTIONARY, 2) => u16::from_be_bytes([bytes[0], bytes[1]]).to_string(),
+                (ATTRIBUTE_BLOCK, 4) => be_u32(bytes).to_string(),
+                (ATTRIBUTE_CHUNKS, length) if length % 4 == 0 => bytes
+                    .chunks_exact(4)
+                    .map(|chunk| be_u32(chunk).to_string())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                (ATTRIBUTE_CHECKSUM, 4) => format!("{:08x}", be_u32(bytes)),
+                (ATTRIBUTE_MODE, 2) => format!("{:o}", u16::from_be_bytes([bytes[0], bytes[1]])),
+                (ATTRIBUTE_MODIFIED, 8) => {
+                    i64::from_be_bytes(bytes.try_into().expect("Expected eight bytes!")).to_string()
+                }
+                (
+                    ATTRIBUTE_DICTIONARY | ATTRIBUTE_BLOCK | ATTRIBUTE_CHUNKS | ATTRIBUTE_CHECKSUM | ATTRIBUTE_MODE
+                    | ATTRIBUTE_MODIFIED,
+                    length,
+                ) => format!("invalid length {length}"),
+                _ => format!("{} bytes", bytes.len()),
+            })?;
+            Ok(())
+        })
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut bytes = self.bytes[..self.bytes.len().min(SHOWN_BYTES)]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<String>>()
+            .join(" ");
+        if self.bytes.len() > SHOWN_BYTES {
+            bytes.push_str(" ..");
+        }
+        write!(
+            f,
+            "{:08x}  {bytes:<26}  {}{}: {}",
+            self.offset,
+            "  ".repeat(self.depth),
+            self.name,
+            self.value
+        )
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Header")?;
+        for field in &self.header {
+            writeln!(f, "{field}")?;
+        }
+        if !self.sealed.is_empty() {
+            writeln!(f, "\nSealed header, offsets into the decrypted content")?;
+            for field in &self.sealed {
+                writeln!(f, "{field}")?;
+            }
+        }
+        if let Some(error) = &self.error {
+            return writeln!(f, "\nStopped: {error}");
+        }
+
+        writeln!(f, "\nData section")?;
+        for region in &self.data {
+            let contents = match region.contents.is_empty() {
+                true => "unreferenced".to_string(),
+                false => region.contents.join(", "),
+            };
+            writeln!(
+                f,
+                "{:08x}  {:>10} bytes  {contents}",
+                self.data_offset + region.offset,
+                region.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |value: bool| match value {
+            true => "yes",
+            false => "no",
+        };
+        writeln!(f, "Version       {}", self.version)?;
+        writeln!(f, "Header        {} bytes", self.header_size)?;
+        writeln!(f, "Data          {} bytes", self.data_size)?;
+        writeln!(f, "Encrypted     {}", yes_no(self.encrypted))?;
+        writeln!(f, "Sealed header {}", yes_no(self.sealed))?;
+        writeln!(f, "Signed        {}", yes_no(self.signed))?;
+        let Some(header) = &self.header else {
+            return writeln!(f, "The header is sealed, the password is needed to list its contents");
+        };
+
+        let mut counts = [0usize; 3];
+        count_entries(&header.entries, &mut counts);
+        writeln!(f, "Dictionaries  {}", header.dictionaries.len())?;
+        writeln!(f, "Blocks        {}", header.blocks.len())?;
+        writeln!(f, "Chunks        {}", header.chunks.len())?;
+        writeln!(f, "Files         {}", counts[0])?;
+        writeln!(f, "Directories   {}", counts[1])?;
+        writeln!(f, "Symlinks      {}", counts[2])
+    }
+}
@@ -1,2 +1,8 @@
 pub mod unpacking;
+pub mod reader;
+pub mod signature;
+pub mod diff;
+pub mod tarball;
+pub mod zipfile;
+pub mod layout;
 mod deserializing;
\ No newline at end of file
@@ -0,0 +1,528 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crc32c::crc32c;
+use ed25519_dalek::VerifyingKey;
+use flate2::read::GzDecoder;
+use zstd::dict::DecoderDictionary;
+use zstd::stream::read::Decoder;
+use crate::deserializing::CatDeserializable;
+use crate::signature::verify;
+use meta::crypto::{Cipher, Piece};
+use meta::error::CatError;
+use meta::metadata::{Compression, Entry, Header, MAGIC_NUMBER};
+use meta::utils::EvalContext;
+use meta::Context;
+
+const DEFAULT_BLOCK_CACHE: usize = 16;
+/// The longest a zstd frame header can be, it holds the content size if the encoder knew it.
+const MAX_FRAME_HEADER: u32 = 18;
+
+/// Random access to the files of an archive. Solid blocks are kept decompressed in a small cache,
+/// so reading the siblings of a file only pays for the block once. An archive opened from a file
+/// is read piece by piece as needed, unless its signature has to be checked.
+pub struct CatReader {
+    header: Header,
+    storage: Storage,
+    dictionaries: Vec<DecoderDictionary<'static>>,
+    cipher: Option<Cipher>,
+    /// Whether the header was stored encrypted.
+    sealed: bool,
+    cache: Mutex<VecDeque<(u32, Arc<Vec<u8>>)>>,
+    cache_size: usize,
+}
+
+/// Where the data section is read from. The offset is where it starts, right after the header.
+enum Storage {
+    Memory { content: Vec<u8>, offset: usize },
+    File { file: Mutex<File>, path: String, offset: u64, size: u64 },
+}
+
+impl CatReader {
+    pub fn open(source: &Path) -> meta::error::Result<CatReader> {
+        CatReader::open_with(source, &Context::default())
+    }
+
+    /// Opens an archive, refusing it unless it carries a valid signature made with the given key.
+    pub fn open_signed(source: &Path, key: &VerifyingKey) -> meta::error::Result<CatReader> {
+        CatReader::from_signed_bytes(read_archive(source)?, key)
+    }
+
+    /// Opens an archive, checking its signature and decrypting it as configured in the context.
+    /// Only the header is read up front, unless the signature is checked, which needs all of it.
+    pub fn open_with(source: &Path, context: &Context) -> meta::error::Result<CatReader> {
+        if context.verifying_key.is_some() {
+            return CatReader::from_bytes_with(read_archive(source)?, context);
+        }
+
+        let path = source.display().to_string();
+        let file = open_archive(source)?;
+        let read_error = |err: std::io::Error| CatError::ErrorReadingFile {
+            path: path.clone(),
+            error: err.to_string(),
+        };
+        let size = file.metadata().map_err(read_error)?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; MAGIC_NUMBER.len()];
+        if reader.read_exact(&mut magic).is_err() || magic != MAGIC_NUMBER {
+            return Err(CatError::InvalidFileType);
+        }
+        let header = Header::deserialize(&mut reader, 0, EvalContext::new("header".to_string()))?;
+        let offset = reader.stream_position().map_err(read_error)?;
+
+        let storage = Storage::File {
+            file: Mutex::new(reader.into_inner()),
+            path: path.clone(),
+            offset,
+            size: size - offset,
+        };
+        CatReader::from_parts(header, storage, context)
+    }
+
+    pub fn from_bytes(content: Vec<u8>) -> meta::error::Result<CatReader> {
+        CatReader::from_bytes_with(content, &Context::default())
+    }
+
+    pub fn from_signed_bytes(content: Vec<u8>, key: &VerifyingKey) -> meta::error::Result<CatReader> {
+        CatReader::from_bytes_with(
+            content,
+            &Context {
+                verifying_key: Some(*key),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn from_bytes_with(mut content: Vec<u8>, context: &Context) -> meta::error::Result<CatReader> {
+        if let Some(key) = &context.verifying_key {
+            let signed = verify(&content, key)?.len();
+            content.truncate(signed);
+        }
+        let (header, data) = split_header(&content)?;
+        let offset = content.len() - data.len();
+        CatReader::from_parts(header, Storage::Memory { content, offset }, context)
+    }
+
+    fn from_parts(mut header: Header, storage: Storage, context: &Context) -> meta::error::Result<CatReader> {
+        let eval_context = EvalContext::new("header".to_string());
+
+        let cipher = match (&header.encryption, &context.password) {
+            (Some(encryption), Some(password)) => Some(Cipher::new(
+                password,
+                encryption,
+                &EvalContext::new("encryption".to_string()),
+            )?),
+            (Some(_), None) => return CatError::MissingPassword.into(),
+            (None, _) => None,
+        };
+        let sealed = header.sealed.is_some();
+        if let (Some(cipher), false) = (&cipher, sealed) {
+            cipher.check(&storage.header()?, &eval_context)?;
+        }
+        if let Some(sealed) = header.sealed.take() {
+            let Some(cipher) = &cipher else {
+                return CatError::InvalidMetadata(eval_context).into();
+            };
+            let inner = cipher.open(&sealed, &Piece::SealedHeader.at(0), &eval_context)?;
+            header = Header {
+                encryption: header.encryption,
+                ..Header::deserialize(&mut inner.as_slice(), 0, eval_context)?
+            };
+        }
+
+        let dictionaries = header
+            .dictionaries
+            .iter()
+            .enumerate()
+            .map(|(i, dictionary)| {
+                let context = EvalContext::new(format!("dictionary {i}"));
+                let content = storage.slice(dictionary.offset, dictionary.size, &context)?;
+                match &cipher {
+                    Some(cipher) => Ok(DecoderDictionary::copy(&cipher.open(
+                        &content,
+                        &Piece::Dictionary.at(dictionary.offset),
+                        &context,
+                    )?)),
+                    None => Ok(DecoderDictionary::copy(&content)),
+                }
+            })
+            .collect::<meta::error::Result<Vec<DecoderDictionary>>>()?;
+
+        Ok(CatReader {
+            header,
+            storage,
+            dictionaries,
+            cipher,
+            sealed,
+            cache: Mutex::new(VecDeque::new()),
+            cache_size: DEFAULT_BLOCK_CACHE,
+        })
+    }
+
+    /// Sets how many decompressed solid blocks are kept around.
+    pub fn with_block_cache(mut self, blocks: usize) -> CatReader {
+        self.cache_size = blocks;
+        self
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The data section following the header, as stored. An archive opened from a file reads it
+    /// in full.
+    pub fn data(&self) -> meta::error::Result<Cow<'_, [u8]>> {
+        match &self.storage {
+            Storage::Memory { content, offset } => Ok(Cow::Borrowed(&content[*offset..])),
+            Storage::File { size, .. } => {
+                let context = EvalContext::new("data".to_string());
+                match u32::try_from(*size) {
+                    Ok(size) => self.storage.slice(0, size, &context),
+                    Err(_) => CatError::InvalidEntryData(context).into(),
+                }
+            }
+        }
+    }
+
+    /// How large the data section is as stored, including anything after the last piece.
+    pub fn data_size(&self) -> u64 {
+        match &self.storage {
+            Storage::Memory { content, offset } => (content.len() - offset) as u64,
+            Storage::File { size, .. } => *size,
+        }
+    }
+
+    /// Writes the first `size` bytes of the data section to `output` as they are stored, without
+    /// holding them in memory.
+    pub fn copy_data(&self, size: u64, output: &mut dyn Write) -> std::io::Result<()> {
+        if size > self.data_size() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        match &self.storage {
+            Storage::Memory { content, offset } => output.write_all(&content[*offset..*offset + size as usize]),
+            Storage::File { file, offset, .. } => {
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(*offset))?;
+                match std::io::copy(&mut (&*file).take(size), output)? {
+                    copied if copied == size => Ok(()),
+                    _ => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                }
+            }
+        }
+    }
+
+    /// A piece of the data section as stored, still compressed and encrypted.
+    pub fn read_raw(&self, offset: u32, size: u32, context: &EvalContext) -> meta::error::Result<Cow<'_, [u8]>> {
+        self.storage.slice(offset, size, context)
+    }
+
+    /// The size of the decompressed content of a file, if it can be told without decoding it.
+    /// Encrypted compressed files can't be looked into, and gzip only records the size modulo
+    /// 4 GiB, so it is a hint rather than a promise.
+    pub fn content_size(&self, entry: &Entry, context: &EvalContext) -> meta::error::Result<Option<u64>> {
+        let Entry::File {
+            offset,
+            size,
+            compression,
+            attributes,
+            ..
+        } = entry
+        else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+        if attributes.block.is_some() || attributes.chunks.is_some() {
+            return Ok(Some(*size as u64));
+        }
+
+        let overhead = self.cipher.as_ref().map_or(0, |_| Cipher::OVERHEAD as u32);
+        match (compression, &self.cipher) {
+            (Compression::None, _) => Ok(size.checked_sub(overhead).map(u64::from)),
+            (Compression::Gzip, None) if *size >= 4 => {
+                let Some(at) = offset.checked_add(size - 4) else {
+                    return CatError::InvalidEntryData(context.clone()).into();
+                };
+                let trailer = self.storage.slice(at, 4, context)?;
+                Ok(Some(u32::from_le_bytes(trailer[..].try_into().unwrap()) as u64))
+            }
+            (Compression::Zstd, None) => {
+                let frame = self.storage.slice(*offset, (*size).min(MAX_FRAME_HEADER), context)?;
+                Ok(zstd::zstd_safe::get_frame_content_size(&frame).ok().flatten())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The cipher the data section is encrypted with, if the archive is encrypted.
+    pub fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_ref()
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    /// Looks up an entry by its `/` separated path inside the archive.
+    pub fn entry(&self, path: &str) -> Option<&Entry> {
+        let mut parts = path.split('/').filter(|part| !part.is_empty());
+        let first = parts.next()?;
+        let mut entry = self.header.entries.iter().find(|entry| entry.name() == first)?;
+        for part in parts {
+            entry = match entry {
+                Entry::Directory { entries, .. } => entries.iter().find(|entry| entry.name() == part)?,
+                Entry::File { .. } | Entry::Symlink { .. } => return None,
+            };
+        }
+        Some(entry)
+    }
+
+    pub fn read(&self, path: &str) -> meta::error::Result<Vec<u8>> {
+        let context = EvalContext::new(path.to_string());
+        match self.entry(path) {
+            Some(entry @ Entry::File { .. }) => self.read_entry(entry, &context),
+            _ => Err(CatError::InvalidInput(path.to_string())),
+        }
+    }
+
+    /// Reads the decompressed content of a file, verifying its checksum if it has one.
+    pub fn read_entry(&self, entry: &Entry, context: &EvalContext) -> meta::error::Result<Vec<u8>> {
+        let content = self.read_unchecked(entry, context)?;
+        if let Entry::File { attributes, .. } = entry
+            && let Some(expected) = attributes.checksum
+        {
+            let actual = crc32c(&content);
+            if actual != expected {
+                return Err(CatError::ChecksumMismatch {
+                    context: context.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(content)
+    }
+
+    fn read_unchecked(&self, entry: &Entry, context: &EvalContext) -> meta::error::Result<Vec<u8>> {
+        let Entry::File {
+            offset,
+            size,
+            compression,
+            attributes,
+            ..
+        } = entry
+        else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+
+        if let Some(chunks) = &attributes.chunks {
+            // The size comes from the header, so the content only grows as far as the chunks go.
+            let mut content = Vec::<u8>::new();
+            for chunk in chunks {
+                let chunk = match self.header.chunks.get(*chunk as usize) {
+                    Some(chunk) => chunk,
+                    None => return CatError::InvalidEntryData(context.clone()).into(),
+                };
+                let data = self.storage.slice(chunk.offset, chunk.size, context)?;
+                let piece = Piece::Chunk.at(chunk.offset);
+                content.extend(self.decode(&data, &piece, &chunk.compression, None, context)?);
+                if content.len() > *size as usize {
+                    return CatError::InvalidEntryData(context.clone()).into();
+                }
+            }
+            if content.len() != *size as usize {
+                return CatError::InvalidEntryData(context.clone()).into();
+            }
+            return Ok(content);
+        }
+
+        match attributes.block {
+            Some(block) => {
+                let block = self.block(block, context)?;
+                slice(&block, *offset, *size, context).map(<[u8]>::to_vec)
+            }
+            None => {
+                let content = self.storage.slice(*offset, *size, context)?;
+                let piece = Piece::File.at(*offset);
+                self.decode(&content, &piece, compression, attributes.dictionary, context)
+            }
+        }
+    }
+
+    /// Reads a file as stored, still compressed and only decrypted. Files in a solid block or
+    /// split into chunks have no stored form of their own and are refused.
+    pub fn read_stored(&self, entry: &Entry, context: &EvalContext) -> meta::error::Result<Vec<u8>> {
+        let Entry::File {
+            offset,
+            size,
+            attributes,
+            ..
+        } = entry
+        else {
+            return CatError::InvalidEntryData(context.clone()).into();
+        };
+        if attributes.block.is_some() || attributes.chunks.is_some() {
+            return CatError::InvalidEntryData(context.clone()).into();
+        }
+
+        let content = self.storage.slice(*offset, *size, context)?;
+        match &self.cipher {
+            Some(cipher) => cipher.open(&content, &Piece::File.at(*offset), context),
+            None => Ok(content.into_owned()),
+        }
+    }
+
+    /// Returns the decompressed solid block, decoding it only if it isn't cached already.
+    pub fn block(&self, index: u32, context: &EvalContext) -> meta::error::Result<Arc<Vec<u8>>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(position) = cache.iter().position(|(i, _)| *i == index) {
+                let cached = cache.remove(position).unwrap();
+                cache.push_back(cached.clone());
+                return Ok(cached.1);
+            }
+        }
+
+        let block = match self.header.blocks.get(index as usize) {
+            Some(block) => block,
+            None => return CatError::InvalidEntryData(context.clone()).into(),
+        };
+        let content = self.storage.slice(block.offset, block.size, context)?;
+        let piece = Piece::Block.at(block.offset);
+        let content = Arc::new(self.decode(&content, &piece, &block.compression, None, context)?);
+
+        if self.cache_size > 0 {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.len() >= self.cache_size {
+                cache.pop_front();
+            }
+            cache.push_back((index, content.clone()));
+        }
+        Ok(content)
+    }
+
+    fn decode(
+        &self,
+        content: &[u8],
+        piece: &[u8],
+        compression: &Compression,
+        dictionary: Option<u16>,
+        context: &EvalContext,
+    ) -> meta::error::Result<Vec<u8>> {
+        let content = match &self.cipher {
+            Some(cipher) => Cow::Owned(cipher.open(content, piece, context)?),
+            None => Cow::Borrowed(content),
+        };
+        let content = content.as_ref();
+        let mut vec = Vec::<u8>::new();
+        let result = match compression {
+            Compression::Gzip => GzDecoder::new(content).read_to_end(&mut vec),
+            Compression::Zstd => match dictionary {
+                Some(index) => match self.dictionaries.get(index as usize) {
+                    Some(dictionary) => Decoder::with_prepared_dictionary(BufReader::new(content), dictionary)
+                        .and_then(|mut decoder| decoder.read_to_end(&mut vec)),
+                    None => return CatError::InvalidEntryData(context.clone()).into(),
+                },
+                None => Decoder::with_buffer(content).and_then(|mut decoder| decoder.read_to_end(&mut vec)),
+            },
+            Compression::None => return Ok(content.to_vec()),
+        };
+        if result.is_err() {
+            return CatError::InvalidEntryData(context.clone()).into();
+        }
+
+        Ok(vec)
+    }
+}
+
+/// Parses the header in front of the data section and returns both. The header is returned as
+/// stored, a sealed header stays encrypted.
+pub fn split_header(content: &[u8]) -> meta::error::Result<(Header, &[u8])> {
+    if !content.starts_with(&MAGIC_NUMBER) {
+        return Err(CatError::InvalidFileType);
+    }
+
+    let mut reader = &content[MAGIC_NUMBER.len()..];
+    let header = Header::deserialize(&mut reader, 0, EvalContext::new("header".to_string()))?;
+    Ok((header, reader))
+}
+
+impl Storage {
+    /// The header as stored, from its version up to the data section.
+    fn header(&self) -> meta::error::Result<Cow<'_, [u8]>> {
+        match self {
+            Storage::Memory { content, offset } => Ok(Cow::Borrowed(&content[MAGIC_NUMBER.len()..*offset])),
+            Storage::File { file, path, offset, .. } => {
+                let mut content = vec![0u8; *offset as usize - MAGIC_NUMBER.len()];
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(MAGIC_NUMBER.len() as u64))
+                    .and_then(|_| file.read_exact(&mut content))
+                    .map_err(|err| CatError::ErrorReadingFile {
+                        path: path.clone(),
+                        error: err.to_string(),
+                    })?;
+                Ok(Cow::Owned(content))
+            }
+        }
+    }
+
+    /// Returns a piece of the data section, refusing one that reaches past its end.
+    fn slice(&self, offset: u32, size: u32, context: &EvalContext) -> meta::error::Result<Cow<'_, [u8]>> {
+        match self {
+            Storage::Memory { content, offset: start } => {
+                slice(&content[*start..], offset, size, context).map(Cow::Borrowed)
+            }
+            Storage::File { file, path, offset: start, size: total } => {
+                if offset as u64 + size as u64 > *total {
+                    return CatError::InvalidEntryData(context.clone()).into();
+                }
+                let mut content = vec![0u8; size as usize];
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(start + offset as u64))
+                    .and_then(|_| file.read_exact(&mut content))
+                    .map_err(|err| CatError::ErrorReadingFile {
+                        path: path.clone(),
+                        error: err.to_string(),
+                    })?;
+                Ok(Cow::Owned(content))
+            }
+        }
+    }
+}
+
+fn open_archive(source: &Path) -> meta::error::Result<File> {
+    if !source.is_file() {
+        return Err(CatError::InvalidInput(source.display().to_string()));
+    }
+
+    File::open(source).map_err(|err| {
+        CatError::FailedToOpenInput {
+            path: source.display().to_string(),
+            error: err.to_string(),
+        }
+    })
+}
+
+fn read_archive(source: &Path) -> meta::error::Result<Vec<u8>> {
+    let mut file = open_archive(source)?;
+    let mut content = Vec::<u8>::new();
+    file.read_to_end(&mut content).map_err(|err| {
+        CatError::ErrorReadingFile {
+            path: source.display().to_string(),
+            error: err.to_string(),
+        }
+    })?;
+
+    Ok(content)
+}
+
+fn slice<'a>(data: &'a [u8], offset: u32, size: u32, context: &EvalContext) -> meta::error::Result<&'a [u8]> {
+    let offset = offset as usize;
+    let end = offset + size as usize;
+    match data.get(offset..end) {
+        Some(content) => Ok(content),
+        None => CatError::InvalidEntryData(context.clone()).into(),
+    }
+}
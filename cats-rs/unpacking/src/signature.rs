@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use ed25519_dalek::VerifyingKey;
+use meta::error::CatError;
+use meta::metadata::{Signature, MAGIC_NUMBER, SIGNATURE_LENGTH};
+
+/// Loads an Ed25519 public key from a PEM file, as written by `openssl pkey -pubout`.
+pub fn load_verifying_key(path: &Path) -> meta::error::Result<VerifyingKey> {
+    let pem = fs::read_to_string(path).map_err(|err| {
+        CatError::FailedToOpenInput {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        }
+    })?;
+    VerifyingKey::from_public_key_pem(&pem).map_err(|err| {
+        CatError::InvalidKey {
+            path: path.display().to_string(),
+            error: err.to_string(),
+        }
+    })
+}
+
+/// Checks that the archive was signed by the given key and returns the signed part of it.
+pub fn verify<'a>(content: &'a [u8], key: &VerifyingKey) -> meta::error::Result<&'a [u8]> {
+    if !content.starts_with(&MAGIC_NUMBER) {
+        return CatError::InvalidFileType.into();
+    }
+
+    let (signed, signature) = Signature::split(content);
+    let signature = match signature {
+        Some(signature) => signature,
+        None => return CatError::MissingSignature.into(),
+    };
+    if signature.public_key != key.to_bytes() {
+        return CatError::InvalidSignature.into();
+    }
+    match key.verify_strict(signed, &ed25519_dalek::Signature::from_bytes(&signature.signature)) {
+        Ok(_) => Ok(signed),
+        Err(_) => CatError::InvalidSignature.into(),
+    }
+}
+
+/// Whether the archive ends in a signature trailer that is valid for its own key. Data that merely
+/// ends like a trailer doesn't count.
+pub fn is_signed(content: &[u8]) -> bool {
+    match Signature::split(content).1 {
+        Some(signature) => {
+            VerifyingKey::from_bytes(&signature.public_key).is_ok_and(|key| verify(content, &key).is_ok())
+        }
+        None => false,
+    }
+}
+
+/// Removes a signature trailer that is valid for its own key. Returns whether one was removed.
+pub fn strip_signature(content: &mut Vec<u8>) -> bool {
+    let signed = is_signed(content);
+    if signed {
+        content.truncate(content.len() - SIGNATURE_LENGTH);
+    }
+    signed
+}
@@ -0,0 +1,79 @@
+use std::io::{empty, Write};
+use meta::error::CatError;
+use meta::metadata::{Attributes, Entry};
+use meta::utils::EvalContext;
+use meta::Context;
+use tar::{Builder, EntryType, Header};
+use crate::reader::CatReader;
+
+/// Writes every entry of the archive to a tar stream, one after another, and returns the output
+/// so a compressing writer can be finished. Files are decoded as they are written, nothing but the
+/// current solid block is kept around.
+pub fn unpack_tar<W: Write>(reader: &CatReader, output: W, context: &Context) -> meta::error::Result<W> {
+    let mut builder = Builder::new(output);
+    for entry in &reader.header().entries {
+        append_entry(&mut builder, reader, entry, "", context)?;
+    }
+
+    builder.into_inner().map_err(|err| CatError::ErrorWritingFile {
+        path: "tar".to_string(),
+        error: err.to_string(),
+    })
+}
+
+fn append_entry<W: Write>(
+    builder: &mut Builder<W>,
+    reader: &CatReader,
+    entry: &Entry,
+    parent: &str,
+    context: &Context,
+) -> meta::error::Result<()> {
+    let path = match parent {
+        "" => entry.name().to_string(),
+        parent => format!("{parent}/{}", entry.name()),
+    };
+    if context.verbose {
+        println!("Writing {path}");
+    }
+    let error = |err: std::io::Error| CatError::ErrorWritingFile {
+        path: path.clone(),
+        error: err.to_string(),
+    };
+
+    match entry {
+        Entry::Directory {
+            entries,
+            attributes,
+            ..
+        } => {
+            let mut header = header(EntryType::Directory, attributes, 0o755);
+            builder.append_data(&mut header, format!("{path}/"), empty()).map_err(error)?;
+            for entry in entries {
+                append_entry(builder, reader, entry, &path, context)?;
+            }
+        }
+        Entry::File { attributes, .. } => {
+            let content = reader.read_entry(entry, &EvalContext::new(path.clone()))?;
+            let mut header = header(EntryType::Regular, attributes, 0o644);
+            header.set_size(content.len() as u64);
+            builder.append_data(&mut header, &path, content.as_slice()).map_err(error)?;
+        }
+        Entry::Symlink { target, attributes, .. } => {
+            let mut header = header(EntryType::Symlink, attributes, 0o777);
+            builder.append_link(&mut header, &path, target).map_err(error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A tar header carrying the stored permissions and modification time, falling back to the given
+/// mode and the epoch.
+fn header(entry_type: EntryType, attributes: &Attributes, mode: u32) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(attributes.mode.map_or(mode, u32::from));
+    header.set_mtime(attributes.modified.unwrap_or(0).max(0) as u64);
+    header.set_size(0);
+    header
+}
@@ -1,83 +1,163 @@
+use std::collections::HashMap;
 use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-use flate2::read::GzDecoder;
+use std::fs::{File, FileTimes, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 use meta::Context;
-use crate::deserializing::CatDeserializable;
+use crate::reader::CatReader;
 use meta::error::CatError;
-use meta::metadata::{Compression, Entry, Header, MAGIC_NUMBER};
+use meta::metadata::{Attributes, Compression, Entry};
 use meta::utils::{validate_name, EvalContext};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 
 pub fn unpack(directory: &Path, source: &Path, context: &Context) -> meta::error::Result<()> {
     if !source.is_file() {
         eprintln!("Can't read input as it's not a file!");
-        return Err(CatError::InvalidInput(source.display().to_string()).into());
+        return Err(CatError::InvalidInput(source.display().to_string()));
     }
 
-    let mut file = File::open(source).map_err(|err| {
-        CatError::FailedToOpenInput {
-            path: source.display().to_string(),
-            error: err.to_string(),
-        }
-            .into()
-    })?;
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(context.jobs)
+        .build()
+        .map_err(|err| CatError::UnableToStartWorkers(err.to_string()))?;
+    let reader = CatReader::open_with(source, context)?.with_block_cache(pool.current_num_threads() * 2);
 
-    let mut file_magic_number = [0u8; 4];
-    file.read_exact(&mut file_magic_number).map_err(|err| {
-        CatError::ErrorReadingFile {
-            path: source.display().to_string(),
-            error: err.to_string(),
-        }
-            .into()
-    })?;
-    if !file_magic_number.eq(&MAGIC_NUMBER) {
-        return Err(CatError::InvalidFileType.into());
+    if fs::create_dir_all(directory).is_err() {
+        return CatError::UnableToCreateDirectory(directory.display().to_string()).into();
     }
+    let mut targets = Targets::default();
+    for entry in &reader.header().entries {
+        create_directories(
+            directory,
+            entry,
+            context,
+            &EvalContext::new("unpacking".to_string()),
+            0,
+            &mut targets,
+        )?
+    }
+    let Targets {
+        files,
+        directories,
+        links,
+    } = targets;
 
-    let header = Header::deserialize(&mut file, EvalContext::new("header".to_string()))?;
-
-    let mut content = Vec::<u8>::new();
-    match file.read_to_end(&mut content) {
-        Err(err) => {
-            return CatError::ErrorReadingFile {
-                path: source.display().to_string(),
-                error: err.to_string(),
+    // Entries sharing a dedup'd blob are grouped so the blob is only decoded once, and blobs
+    // living in the same solid block are grouped so the block is only decompressed once.
+    let mut units = Vec::<Vec<(&Entry, Vec<FileTarget>)>>::new();
+    let mut blob_indices = HashMap::<Blob, (usize, usize)>::new();
+    let mut block_indices = HashMap::<u32, usize>::new();
+    for file in files {
+        match blob_indices.get(&file.blob) {
+            Some((unit, index)) => units[*unit][*index].1.push(file),
+            None => {
+                let unit = match file.blob.block {
+                    Some(block) => *block_indices.entry(block).or_insert_with(|| {
+                        units.push(Vec::new());
+                        units.len() - 1
+                    }),
+                    None => {
+                        units.push(Vec::new());
+                        units.len() - 1
+                    }
+                };
+                blob_indices.insert(file.blob.clone(), (unit, units[unit].len()));
+                units[unit].push((file.entry, vec![file]));
             }
-                .into();
         }
-        _ => {}
     }
-    for entry in &header.entries {
-        unpack_entry(
-            &content,
-            &directory,
-            &entry,
-            &context,
-            &EvalContext::new("unpacking".to_string()),
-        )?
+
+    pool.install(|| {
+        units.par_iter().try_for_each(|unit| {
+            for (entry, targets) in unit {
+                let content = reader.read_entry(entry, &targets[0].eval_context)?;
+                unpack_entry(&content, targets, context)?;
+            }
+            Ok(())
+        })
+    })?;
+
+    // Links are created last, so no file can be written through one of them.
+    for link in links {
+        create_link(&link, context)?;
+    }
+
+    // Writing the files touches their directories, so those are restored afterwards. They were
+    // collected children first, so a parent losing its write or search bit doesn't block them.
+    for (path, attributes) in &directories {
+        restore_attributes(path, attributes, context)?;
     }
 
     Ok(())
 }
 
-fn unpack_entry(
-    data: &Vec<u8>,
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Blob {
+    offset: u32,
+    size: u32,
+    compression: Compression,
+    dictionary: Option<u16>,
+    block: Option<u32>,
+    chunks: Option<Vec<u32>>,
+    checksum: Option<u32>,
+}
+
+struct FileTarget<'a> {
+    path: PathBuf,
+    entry: &'a Entry,
+    blob: Blob,
+    eval_context: EvalContext,
+}
+
+struct LinkTarget<'a> {
+    path: PathBuf,
+    target: &'a str,
+    /// How many directories the link is nested in below the destination.
+    depth: usize,
+}
+
+#[derive(Default)]
+struct Targets<'a> {
+    files: Vec<FileTarget<'a>>,
+    directories: Vec<(PathBuf, &'a Attributes)>,
+    links: Vec<LinkTarget<'a>>,
+}
+
+fn create_directories<'a>(
     path: &Path,
-    entry: &Entry,
+    entry: &'a Entry,
     context: &Context,
     eval_context: &EvalContext,
+    depth: usize,
+    targets: &mut Targets<'a>,
 ) -> meta::error::Result<()> {
     match entry {
-        Entry::Directory { name, entries } => {
+        Entry::Directory {
+            name,
+            entries,
+            attributes,
+        } => {
             let mut new_path = PathBuf::from(path);
-            new_path.push(validate_name(name.clone(), &eval_context)?);
+            new_path.push(validate_name(name.clone(), eval_context)?);
             if context.verbose {
                 println!("Unpacking {}", new_path.display());
             }
+            if fs::create_dir_all(&new_path).is_err() {
+                return CatError::UnableToCreateDirectory(new_path.display().to_string()).into();
+            }
             for entry in entries {
-                unpack_entry(data, new_path.as_path(), entry, context, &eval_context.push(name.clone()))?
+                create_directories(
+                    new_path.as_path(),
+                    entry,
+                    context,
+                    &eval_context.push(name.clone()),
+                    depth + 1,
+                    targets,
+                )?
             }
+            targets.directories.push((new_path, attributes));
 
             Ok(())
         }
@@ -86,49 +166,143 @@ fn unpack_entry(
             offset,
             size,
             compression,
+            attributes,
         } => {
             let mut new_path = PathBuf::from(path);
             let eval_context = eval_context.push(name.clone());
             new_path.push(validate_name(name.clone(), &eval_context)?);
+            targets.files.push(FileTarget {
+                path: new_path,
+                entry,
+                blob: Blob {
+                    offset: *offset,
+                    size: *size,
+                    compression: compression.clone(),
+                    dictionary: attributes.dictionary,
+                    block: attributes.block,
+                    chunks: attributes.chunks.clone(),
+                    checksum: attributes.checksum,
+                },
+                eval_context,
+            });
 
-            let offset = *offset as usize;
-            let end = offset + *size as usize;
-            let content = &data[offset..end];
+            Ok(())
+        }
+        Entry::Symlink { name, target, .. } => {
+            let mut new_path = PathBuf::from(path);
+            new_path.push(validate_name(name.clone(), &eval_context.push(name.clone()))?);
+            targets.links.push(LinkTarget {
+                path: new_path,
+                target,
+                depth,
+            });
 
-            let mut vec = Vec::<u8>::new();
-            let actual_content = match compression {
-                Compression::Gzip => {
-                    if GzDecoder::new(content).read_to_end(&mut vec).is_err() {
-                        return CatError::InvalidEntryData(eval_context.clone()).into();
-                    }
-                    &vec
+            Ok(())
+        }
+    }
+}
+
+/// Creates a symbolic link, unless its target could point outside the destination.
+fn create_link(link: &LinkTarget, context: &Context) -> meta::error::Result<()> {
+    if !stays_inside(link.target, link.depth) {
+        eprintln!(
+            "Skipping link {} as its target {} leaves the destination",
+            link.path.display(),
+            link.target
+        );
+        return Ok(());
+    }
+    if context.verbose {
+        println!("Unpacking {} -> {}", link.path.display(), link.target);
+    }
+
+    let error = |err: std::io::Error| CatError::ErrorWritingFile {
+        path: link.path.display().to_string(),
+        error: err.to_string(),
+    };
+    if fs::symlink_metadata(&link.path).is_ok_and(|metadata| metadata.is_symlink()) {
+        fs::remove_file(&link.path).map_err(error)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(link.target, &link.path).map_err(error)?;
+    #[cfg(not(unix))]
+    eprintln!("Skipping link {} as links are only supported on unix", link.path.display());
+
+    Ok(())
+}
+
+/// Whether a link target resolves inside the destination. Only leading `..` are allowed, so a
+/// target can't climb back out after passing through another link.
+fn stays_inside(target: &str, depth: usize) -> bool {
+    let mut parents = 0;
+    let mut descended = false;
+    for component in Path::new(target).components() {
+        match component {
+            Component::ParentDir if !descended => parents += 1,
+            Component::Normal(_) => descended = true,
+            Component::CurDir => {}
+            _ => return false,
+        }
+    }
+
+    !target.is_empty() && parents <= depth
+}
+
+fn unpack_entry(content: &[u8], targets: &[FileTarget], context: &Context) -> meta::error::Result<()> {
+    for target in targets {
+        let path = target.path.as_path();
+        if context.verbose {
+            println!("Unpacking {}", path.display());
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(content))
+            .map_err(|err| {
+                CatError::ErrorWritingFile {
+                    path: path.display().to_string(),
+                    error: err.to_string(),
                 }
-                Compression::None => content,
-            };
+            })?;
+        if let Entry::File { attributes, .. } = target.entry {
+            restore_attributes(path, attributes, context)?;
+        }
+    }
 
-            if context.verbose {
-                println!("Unpacking {}", new_path.display());
-            }
+    Ok(())
+}
 
-            let path = new_path.as_path();
-            let parent = path.parent().unwrap();
-            if fs::create_dir_all(parent).is_err() {
-                return CatError::UnableToCreateDirectory(parent.display().to_string()).into();
-            }
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(path)
-                .unwrap()
-                .write_all(actual_content)
-                .map_err(|err| {
-                    CatError::ErrorWritingFile {
-                        path: path.display().to_string(),
-                        error: err.to_string(),
-                    }
-                        .into()
-                })
+/// Applies the stored modification time and permissions, as far as they should be restored.
+fn restore_attributes(path: &Path, attributes: &Attributes, context: &Context) -> meta::error::Result<()> {
+    let error = |err: std::io::Error| CatError::ErrorWritingFile {
+        path: path.display().to_string(),
+        error: err.to_string(),
+    };
+
+    if context.times
+        && let Some(modified) = attributes.modified
+    {
+        let time = match u64::try_from(modified) {
+            Ok(seconds) => UNIX_EPOCH.checked_add(Duration::from_secs(seconds)),
+            Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(modified.unsigned_abs())),
+        };
+        if let Some(time) = time {
+            File::open(path)
+                .and_then(|file| file.set_times(FileTimes::new().set_modified(time)))
+                .map_err(error)?;
         }
     }
+
+    #[cfg(unix)]
+    if context.permissions
+        && let Some(mode) = attributes.mode
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode as u32 & 0o777)).map_err(error)?;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,332 @@
+use std::io::{self, Write};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Crc;
+use meta::error::CatError;
+use meta::metadata::{Attributes, Compression, Entry};
+use meta::utils::{civil_time, EvalContext};
+use meta::Context;
+use crate::reader::CatReader;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// The most a deflate stream can expand to per byte, so shorter streams always fit the 32 bit size
+/// in the gzip trailer.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+/// Writes every entry of the archive to a zip archive and returns the output. Files stored
+/// uncompressed stay uncompressed, gzip compressed files keep their deflate stream as stored and
+/// everything else is deflate compressed. Modification times are written to the extended timestamp
+/// field as well, as the DOS time only has a two second resolution.
+pub fn unpack_zip<W: Write>(reader: &CatReader, output: W, context: &Context) -> meta::error::Result<W> {
+    let mut writer = ZipWriter {
+        output,
+        position: 0,
+        records: Vec::new(),
+    };
+    for entry in &reader.header().entries {
+        append_entry(&mut writer, reader, entry, "", context)?;
+    }
+
+    writer.finish().map_err(|err| CatError::ErrorWritingFile {
+        path: "zip".to_string(),
+        error: err.to_string(),
+    })
+}
+
+fn append_entry<W: Write>(
+    writer: &mut ZipWriter<W>,
+    reader: &CatReader,
+    entry: &Entry,
+    parent: &str,
+    context: &Context,
+) -> meta::error::Result<()> {
+    let path = match parent {
+        "" => entry.name().to_string(),
+        parent => format!("{parent}/{}", entry.name()),
+    };
+    if context.verbose {
+        println!("Writing {path}");
+    }
+    let error = |err: io::Error| CatError::ErrorWritingFile {
+        path: path.clone(),
+        error: err.to_string(),
+    };
+
+    match entry {
+        Entry::Directory {
+            entries,
+            attributes,
+            ..
+        } => {
+            let record = Record::new(format!("{path}/"), 0o040000 | mode(attributes, 0o755), attributes);
+            writer.add(record, &[]).map_err(error)?;
+            for entry in entries {
+                append_entry(writer, reader, entry, &path, context)?;
+            }
+        }
+        Entry::File {
+            compression,
+            attributes,
+            ..
+        } => {
+            let context = EvalContext::new(path.clone());
+            let mut record = Record::new(path.clone(), 0o100000 | mode(attributes, 0o644), attributes);
+            let stored = match compression {
+                Compression::Gzip if attributes.block.is_none() && attributes.chunks.is_none() => {
+                    Some(reader.read_stored(entry, &context)?)
+                }
+                _ => None,
+            };
+
+            match stored.as_deref().and_then(split_gzip) {
+                Some((deflate, crc, size)) => {
+                    record.method = DEFLATED;
+                    record.crc = crc;
+                    record.size = size;
+                    writer.add(record, deflate).map_err(error)?;
+                }
+                None => {
+                    let content = reader.read_entry(entry, &context)?;
+                    let mut crc = Crc::new();
+                    crc.update(&content);
+                    record.crc = crc.sum();
+                    record.size = content.len() as u64;
+                    match compression {
+                        Compression::None if attributes.block.is_none() => writer.add(record, &content),
+                        _ => {
+                            record.method = DEFLATED;
+                            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                            encoder
+                                .write_all(&content)
+                                .and_then(|_| encoder.finish())
+                                .and_then(|deflate| writer.add(record, &deflate))
+                        }
+                    }
+                    .map_err(error)?;
+                }
+            }
+        }
+        Entry::Symlink { target, attributes, .. } => {
+            let mut record = Record::new(path.clone(), 0o120000 | mode(attributes, 0o777), attributes);
+            let mut crc = Crc::new();
+            crc.update(target.as_bytes());
+            record.crc = crc.sum();
+            record.size = target.len() as u64;
+            writer.add(record, target.as_bytes()).map_err(error)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn mode(attributes: &Attributes, mode: u32) -> u32 {
+    attributes.mode.map_or(mode, u32::from) & 0o7777
+}
+
+/// Splits a gzip member into its deflate stream, the CRC-32 and the size of the content. Returns
+/// nothing if it isn't a single well-formed member, or if the size may have wrapped around.
+fn split_gzip(gzip: &[u8]) -> Option<(&[u8], u32, u64)> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if gzip.len() < 18 || gzip[..3] != [0x1f, 0x8b, 0x08] || gzip[3] & 0xe0 != 0 {
+        return None;
+    }
+    let flags = gzip[3];
+    let mut start = 10;
+    if flags & FEXTRA != 0 {
+        let length = u16::from_le_bytes(gzip.get(start..start + 2)?.try_into().ok()?);
+        start += 2 + length as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            start += gzip.get(start..)?.iter().position(|byte| *byte == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        start += 2;
+    }
+
+    let end = gzip.len() - 8;
+    let deflate = gzip.get(start..end)?;
+    let crc = u32::from_le_bytes(gzip[end..end + 4].try_into().ok()?);
+    let size = u32::from_le_bytes(gzip[end + 4..].try_into().ok()?) as u64;
+    if (deflate.len() as u64) < u32::MAX as u64 / MAX_DEFLATE_RATIO {
+        return Some((deflate, crc, size));
+    }
+
+    // Long enough to hold more than 4 GiB, so the size is counted instead of trusting the trailer.
+    let size = io::copy(&mut DeflateDecoder::new(deflate), &mut io::sink()).ok()?;
+    (size as u32 == u32::from_le_bytes(gzip[end + 4..].try_into().ok()?)).then_some((deflate, crc, size))
+}
+
+/// An entry as it is listed in the central directory.
+struct Record {
+    name: String,
+    /// The unix file type and permissions.
+    mode: u32,
+    modified: Option<i64>,
+    method: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+impl Record {
+    fn new(name: String, mode: u32, attributes: &Attributes) -> Record {
+        Record {
+            name,
+            mode,
+            modified: attributes.modified,
+            method: STORED,
+            crc: 0,
+            compressed: 0,
+            size: 0,
+            offset: 0,
+        }
+    }
+
+    fn header(&self, central: bool) -> Vec<u8> {
+        let large = self.size >= u32::MAX as u64 || self.compressed >= u32::MAX as u64;
+        let far = central && self.offset >= u32::MAX as u64;
+        let version: u16 = if large || far { 45 } else { 20 };
+        let (time, date) = dos_time(self.modified);
+
+        let mut zip64 = Vec::<u8>::new();
+        if large {
+            zip64.extend_from_slice(&self.size.to_le_bytes());
+            zip64.extend_from_slice(&self.compressed.to_le_bytes());
+        }
+        if far {
+            zip64.extend_from_slice(&self.offset.to_le_bytes());
+        }
+        let mut extra = Vec::<u8>::new();
+        if !zip64.is_empty() {
+            extra.extend_from_slice(&0x0001u16.to_le_bytes());
+            extra.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+            extra.extend_from_slice(&zip64);
+        }
+        // Extended timestamp, holding only the modification time.
+        if let Some(modified) = self.modified.and_then(|modified| i32::try_from(modified).ok()) {
+            extra.extend_from_slice(&0x5455u16.to_le_bytes());
+            extra.extend_from_slice(&5u16.to_le_bytes());
+            extra.push(0x01);
+            extra.extend_from_slice(&modified.to_le_bytes());
+        }
+
+        let clamp = |value: u64| if large { u32::MAX } else { value as u32 };
+        let mut header = Vec::<u8>::with_capacity(46 + self.name.len() + extra.len());
+        match central {
+            true => {
+                header.extend_from_slice(&0x02014b50u32.to_le_bytes());
+                header.extend_from_slice(&(0x0300 | version).to_le_bytes());
+            }
+            false => header.extend_from_slice(&0x04034b50u32.to_le_bytes()),
+        }
+        header.extend_from_slice(&version.to_le_bytes());
+        header.extend_from_slice(&(if self.name.is_ascii() { 0u16 } else { 0x0800 }).to_le_bytes());
+        header.extend_from_slice(&self.method.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&self.crc.to_le_bytes());
+        header.extend_from_slice(&clamp(self.compressed).to_le_bytes());
+        header.extend_from_slice(&clamp(self.size).to_le_bytes());
+        header.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        if central {
+            let directory = if self.mode & 0o170000 == 0o040000 { 0x10 } else { 0 };
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&(self.mode << 16 | directory).to_le_bytes());
+            header.extend_from_slice(&(if far { u32::MAX } else { self.offset as u32 }).to_le_bytes());
+        }
+        header.extend_from_slice(self.name.as_bytes());
+        header.extend_from_slice(&extra);
+        header
+    }
+}
+
+/// Packs the time into the DOS date and time fields, falling back to the earliest time they can
+/// represent.
+fn dos_time(modified: Option<i64>) -> (u16, u16) {
+    let (year, month, day, hour, minute, second) = modified
+        .map(civil_time)
+        .filter(|(year, ..)| (1980..=2107).contains(year))
+        .unwrap_or((1980, 1, 1, 0, 0, 0));
+    let time = (hour as u16) << 11 | (minute as u16) << 5 | ((second as u16) / 2);
+    let date = ((year - 1980) as u16) << 9 | (month as u16) << 5 | day as u16;
+    (time, date)
+}
+
+/// Writes a zip archive front to back, so the output doesn't need to be seekable. Sizes and
+/// checksums are known before an entry is written, so no data descriptors are needed.
+struct ZipWriter<W: Write> {
+    output: W,
+    position: u64,
+    records: Vec<Record>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    fn add(&mut self, mut record: Record, data: &[u8]) -> io::Result<()> {
+        record.offset = self.position;
+        record.compressed = data.len() as u64;
+        self.write(&record.header(false))?;
+        self.write(data)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let start = self.position;
+        let records = std::mem::take(&mut self.records);
+        for record in &records {
+            self.write(&record.header(true))?;
+        }
+        let count = records.len() as u64;
+        let size = self.position - start;
+
+        let mut end = Vec::<u8>::new();
+        let zip64 = count >= u16::MAX as u64 || size >= u32::MAX as u64 || start >= u32::MAX as u64;
+        if zip64 {
+            let record = self.position;
+            end.extend_from_slice(&0x06064b50u32.to_le_bytes());
+            end.extend_from_slice(&44u64.to_le_bytes());
+            end.extend_from_slice(&(0x0300u16 | 45).to_le_bytes());
+            end.extend_from_slice(&45u16.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&count.to_le_bytes());
+            end.extend_from_slice(&size.to_le_bytes());
+            end.extend_from_slice(&start.to_le_bytes());
+
+            end.extend_from_slice(&0x07064b50u32.to_le_bytes());
+            end.extend_from_slice(&0u32.to_le_bytes());
+            end.extend_from_slice(&record.to_le_bytes());
+            end.extend_from_slice(&1u32.to_le_bytes());
+        }
+
+        let count = if zip64 { u16::MAX } else { count as u16 };
+        end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&(if zip64 { u32::MAX } else { size as u32 }).to_le_bytes());
+        end.extend_from_slice(&(if zip64 { u32::MAX } else { start as u32 }).to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.write(&end)?;
+        Ok(self.output)
+    }
+}